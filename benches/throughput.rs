@@ -0,0 +1,143 @@
+//! Measures how fast the engine simulates and searches, using whichever allocator the crate is built with.
+//!
+//! Run with `cargo bench --bench throughput`, and again with `--features wee_alloc` to compare allocators. The unit
+//! tests install an allocator that counts allocations, so timings are kept out of them and all live here.
+
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::time::{ Duration, Instant };
+use uttt_ai::ai::{ ActionTree, Game };
+use uttt_ai::game::TicTacToe;
+
+// Names the allocator the benchmarks are running with, for their reports
+fn allocator_name() -> &'static str {
+    if cfg!(feature = "wee_alloc") { "wee_alloc" } else { "default" }
+}
+
+// The first search step on a new tree only simulates the root, so it measures the rollouts alone
+fn bench_simulate() {
+    const SIMS: u32 = 20_000;
+
+    // The mid-game position is 30 moves into a game played by the rollout policy
+    let mut mid_game = TicTacToe::new();
+    let mut rng = SmallRng::seed_from_u64(1);
+    for _ in 0..30 {
+        let action = mid_game.rollout_action(&mut rng).unwrap();
+        mid_game.do_action_mut(&action);
+    }
+
+    let positions = [("empty board", TicTacToe::new()), ("mid-game", mid_game)];
+    for (name, state) in positions.iter() {
+        let mut tree = ActionTree::with_seed(state.clone(), 0);
+        let start = Instant::now();
+        tree.do_search_step(black_box(SIMS));
+        let elapsed = start.elapsed();
+        println!(
+            "simulate, {}, {} allocator: {:.0} sims/s",
+            name, allocator_name(), SIMS as f64 / elapsed.as_secs_f64()
+        );
+    }
+}
+
+fn bench_do_search_step() {
+    const STEPS: u32 = 20_000;
+    for &sims_per_step in [1, 10].iter() {
+        let mut tree = ActionTree::new(TicTacToe::new());
+        let start = Instant::now();
+        for _ in 0..STEPS {
+            tree.do_search_step(sims_per_step);
+        }
+        let elapsed = start.elapsed();
+        println!(
+            "do_search_step({}), {} allocator: {:.0} steps/s",
+            sims_per_step, allocator_name(), STEPS as f64 / elapsed.as_secs_f64()
+        );
+    }
+}
+
+// Cutting rollouts off after a few moves and scoring the position by sub-boards won should make each step faster
+fn bench_rollout_depth() {
+    const STEPS: u32 = 2_000;
+    for &max_depth in [None, Some(6)].iter() {
+        let mut tree = ActionTree::with_seed(TicTacToe::new(), 4);
+        tree.set_rollout_depth(max_depth);
+        let start = Instant::now();
+        for _ in 0..STEPS {
+            tree.do_search_step(10);
+        }
+        let elapsed = start.elapsed();
+        let depth = max_depth.map_or_else(|| "full".to_owned(), |depth| depth.to_string());
+        println!("rollout depth {}: {:.0} steps/s", depth, STEPS as f64 / elapsed.as_secs_f64());
+    }
+}
+
+// Every move checks its sub-board for a win with the lookup table, so replaying games measures the check along with the
+// rest of a move
+fn bench_do_action() {
+    const GAMES: u32 = 2_000;
+
+    // Record the games first, so that choosing moves isn't timed
+    let mut rng = SmallRng::seed_from_u64(2);
+    let games = (0..GAMES).map(|_| {
+        let mut state = TicTacToe::new();
+        let mut moves = Vec::new();
+        while let Some(action) = state.rollout_action(&mut rng) {
+            moves.push(action);
+            state.do_action_mut(&action);
+        }
+        moves
+    }).collect::<Vec<Vec<u8>>>();
+
+    let start = Instant::now();
+    for moves in games.iter() {
+        let mut state = TicTacToe::new();
+        for action in moves {
+            state.do_action_mut(black_box(action));
+        }
+        black_box(state.winner());
+    }
+    let elapsed = start.elapsed();
+    let total_moves = games.iter().map(Vec::len).sum::<usize>();
+    println!("do_action_mut: {:.1} ns/move", elapsed.as_nanos() as f64 / total_moves as f64);
+}
+
+// Seeding the root's children with no visits expands it without searching
+fn bench_expand() {
+    const EXPANSIONS: u32 = 2_000;
+    let mut elapsed = Duration::ZERO;
+    for _ in 0..EXPANSIONS {
+        let mut tree = ActionTree::new(TicTacToe::new());
+        let start = Instant::now();
+        tree.seed_children(HashMap::new()).unwrap();
+        elapsed += start.elapsed();
+        black_box(tree.node_count());
+    }
+    println!(
+        "expand, {} allocator: {:.1} ns/child",
+        allocator_name(), elapsed.as_nanos() as f64 / (EXPANSIONS * 81) as f64
+    );
+}
+
+#[cfg(feature = "parallel")]
+fn bench_parallel_simulate() {
+    const SIMS: u32 = 40_000;
+    for &threads in [1, 2, 4, 8].iter() {
+        let mut tree = ActionTree::with_seed(TicTacToe::new(), 0);
+        let start = Instant::now();
+        tree.do_parallel_search_step(SIMS, threads);
+        let elapsed = start.elapsed();
+        println!("simulate_parallel, {} threads: {:.0} sims/s", threads, SIMS as f64 / elapsed.as_secs_f64());
+    }
+}
+
+fn main() {
+    bench_simulate();
+    bench_do_search_step();
+    bench_rollout_depth();
+    bench_do_action();
+    bench_expand();
+    #[cfg(feature = "parallel")]
+    bench_parallel_simulate();
+}
@@ -1,10 +1,116 @@
 use std::collections::{ HashMap, HashSet };
-use std::hash::Hash;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt::{ Display, Write };
+use std::hash::{ BuildHasherDefault, Hash };
+use std::time::Duration;
 use slab::Slab;
 use rand::prelude::*;
 use rand::rngs::SmallRng;
+use wasm_bindgen::prelude::*;
+use crate::timer::Stopwatch;
 
-const EXPLORATION_FACTOR: f64 = 1.4142135623730950488016887242097; // sqrt(2)
+// Maps actions to child node IDs. This uses a hasher with fixed keys so that children are iterated in the same order
+// every time, which keeps searches with the same seed reproducible.
+type ChildMap<A> = HashMap<A, usize, BuildHasherDefault<DefaultHasher>>;
+
+// The default exploration constant used in the UCT score
+const EXPLORATION_FACTOR: f64 = std::f64::consts::SQRT_2;
+
+// The margin of error subtracted from winrates for `FinalMoveSelection::MaxLowerBound` is sqrt(this / visits), which is
+// Hoeffding's bound for the true winrate to be at least the result 95% of the time
+const LOWER_BOUND_FACTOR: f64 = 1.4978661367769954; // ln(20) / 2
+
+// The number of simulations from the root needed before `should_resign` and `should_claim_draw` trust the statistics
+const MIN_VISITS_TO_CONCEDE: u32 = 1000;
+
+// The number of simulations every child of a node needs before `BackupPolicy::Max` values the node by its best child.
+// Replies that look bad are rarely revisited, so waiting for more than one simulation of each would hold it back.
+const MAX_BACKUP_MIN_VISITS: u32 = 1;
+
+// The number of simulations each search step runs in `parallel_search`
+#[cfg(feature = "parallel")]
+const PARALLEL_SIMS_PER_STEP: u32 = 10;
+
+/// How the search decides which node to explore next.
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SelectionPolicy {
+    /// UCT, which uses the tree's exploration constant for every action
+    Uct,
+    /// UCB1-Tuned, which explores actions less when their results vary less. This ignores the exploration constant.
+    Ucb1Tuned,
+    /// PUCT, which explores actions in proportion to their prior probability from `Game::action_priors`. This uses the
+    /// exploration constant as the weight of the priors.
+    Puct
+}
+
+/// How the search picks the action to recommend once it's done.
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FinalMoveSelection {
+    /// The action with the most simulations. This is the usual choice for MCTS, since the search spends the most time
+    /// on the actions that look best, and an action with few simulations can't have looked good for long.
+    MostVisited,
+    /// The action with the highest winrate. This can pick an action that was only simulated a few times and got lucky.
+    MaxWinrate,
+    /// The action whose winrate is highest after subtracting a margin of error that shrinks as it's simulated more,
+    /// which trades off between the other two.
+    MaxLowerBound
+}
+
+/// How the results of simulations are combined into the value of each node, which selection and `get_best_action` use.
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BackupPolicy {
+    /// The node's winrate over every simulation run through it
+    Average,
+    /// Once every action from the node has been simulated, the value of the best action for the player to move, as in
+    /// minimax. This spots forced sequences sooner in endgames, since bad replies the opponent wouldn't play stop
+    /// counting. It assumes two players, and that results worth `x` to one player are worth `1 - x` to the other, which
+    /// only holds exactly with `Scoring::draws_as_half_wins`.
+    Max
+}
+
+/// The game-theoretic result of a position for the player to move, once the solver has proven it.
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ProvenOutcome {
+    /// The player to move can force a win
+    Win,
+    /// Another player can force a win, whatever the player to move does
+    Loss,
+    /// The best the player to move can force is a draw
+    Draw
+}
+
+/// How many points each player earns at the end of a simulated game. Winrates are the share of the available points that
+/// a player earned, so a higher value for `draw` makes the AI more willing to settle for a draw.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Scoring {
+    /// Points given to the winner of a game
+    pub win: u32,
+    /// Points given to every player when a game ends without a winner
+    pub draw: u32
+}
+
+impl Default for Scoring {
+    fn default() -> Self {
+        Scoring { win: 10, draw: 1 }
+    }
+}
+
+impl Scoring {
+    /// Scoring where a draw is worth half a win to every player, so that winrates are expected scores from 0 to 1
+    /// instead of leaning toward decisive results.
+    pub const fn draws_as_half_wins() -> Self {
+        Scoring { win: 2, draw: 1 }
+    }
+}
 
 /// A problem which agents can work on. An object implementing this trait should contain the system's state.
 pub trait Game: Clone {
@@ -14,104 +120,668 @@ pub trait Game: Clone {
     /// Returns a list of actions that can be taken on the game in its current state
     fn available_actions(&self) -> &[Self::Action];
     /// Performs an action immutably, returning a copy of this object that has had the action applied to it.
-    /// Assumes that the given action is valid (i.e. it was returned from Game::available_actions).
-    fn do_action(&self, action: &Self::Action) -> Box<Self>;
+    /// Assumes that the given action is valid (i.e. it was returned from Game::available_actions). The copy is returned
+    /// by value, so expanding a node doesn't allocate anything beyond what cloning the state does.
+    fn do_action(&self, action: &Self::Action) -> Self {
+        let mut next = self.clone();
+        next.do_action_mut(action);
+        next
+    }
     /// Performs an action mutably, applying the action to this object.
     /// Assumes that the given action is valid (i.e. it was returned from Game::available_actions)
     fn do_action_mut(&mut self, action: &Self::Action);
+    /// Chooses the next action to take while simulating a game, or returns None if the game is over. By default this
+    /// picks uniformly at random, but games can override it to simulate more realistic play.
+    fn rollout_action<R: Rng>(&self, rng: &mut R) -> Option<Self::Action> {
+        self.available_actions().choose(rng).cloned()
+    }
+    /// Estimates how likely each available action is to be the best one, for `SelectionPolicy::Puct`. The
+    /// probabilities should add up to 1. By default every action is equally likely.
+    fn action_priors(&self) -> HashMap<Self::Action, f64> {
+        let actions = self.available_actions();
+        actions.iter().map(|action| (action.clone(), 1.0 / actions.len() as f64)).collect()
+    }
     /// Gets a list of all players in the game
     fn get_players(&self) -> &[Self::Player];
-    /// Returns the player that is currently allowed to make a move
+    /// Returns the player that is currently allowed to make a move. Each node in the search tree is scored for the player
+    /// who chose the action leading to it, which is the current player of its parent's state, so this must be correct
+    /// for every state and not just assume that two players alternate turns.
     fn current_player(&self) -> Self::Player;
     /// If a player has won the game then this returns the winner, otherwise it returns None.
     fn winner(&self) -> Option<Self::Player>;
     /// Returns whether the game has ended
     fn game_over(&self) -> bool { self.available_actions().is_empty() }
+    /// Returns the number of parts of the board that can be won separately from the game itself, such as the sub-boards
+    /// in Ultimate TicTacToe. Games without such regions don't need to implement this or `region_winner`.
+    fn region_count(&self) -> usize { 0 }
+    /// Returns who has won the region with the given index, if anyone
+    fn region_winner(&self, _region: usize) -> Option<Self::Player> { None }
+    /// Estimates the points each player would earn if the game were played out from here, for simulations that are
+    /// stopped early (see `ActionTree::set_rollout_depth`). The points should add up to `scoring.win`, like a game with
+    /// a winner, unless the game is likely to be drawn. By default this plays the game out with `rollout_action`.
+    fn evaluate<R: Rng>(&self, rng: &mut R, scoring: Scoring) -> HashMap<Self::Player, u32> {
+        let mut state = self.clone();
+        while let Some(action) = state.rollout_action(rng) {
+            state.do_action_mut(&action);
+        }
+        let winner = state.winner();
+        self.get_players().iter()
+            .map(|player| {
+                let earned = match &winner {
+                    Some(winner) if winner == player => scoring.win,
+                    Some(_) => 0,
+                    None => scoring.draw
+                };
+                (player.clone(), earned)
+            })
+            .collect()
+    }
+    /// Returns a string that identifies this position, used to look it up in an `OpeningBook`. Games that don't
+    /// implement this can't use opening books.
+    fn position_key(&self) -> Option<String> { None }
+    /// Returns whether no moves have been played yet. Games that don't implement this never count as just started, so
+    /// `ActionTree::randomize_first_move` always plays their best move.
+    fn is_initial_state(&self) -> bool { false }
+}
+
+/// An error produced when an action that isn't legal in the current position is played on an `ActionTree`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct IllegalAction;
+
+impl Display for IllegalAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "the action is not legal in the current position")
+    }
+}
+
+/// An error produced when `ActionTree::seed_children` is given more visits than the tree's statistics can hold.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TooManyVisits;
+
+impl Display for TooManyVisits {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "the seeded visits are worth more points than the tree can count")
+    }
+}
+
+/// The actions to play in well-known positions, keyed by `Game::position_key`, which let the AI play strong openings
+/// instantly without searching.
+#[derive(Clone, Debug)]
+pub struct OpeningBook<A> {
+    moves: HashMap<String, A>
+}
+
+impl<A> Default for OpeningBook<A> {
+    fn default() -> Self {
+        OpeningBook { moves: HashMap::new() }
+    }
+}
+
+impl<A> OpeningBook<A> {
+    pub fn new() -> Self {
+        OpeningBook::default()
+    }
+
+    /// Sets the action to play in the position with the given key, replacing any action that was already booked.
+    pub fn insert(&mut self, key: String, action: A) {
+        self.moves.insert(key, action);
+    }
+
+    /// Gets the action booked for the position with the given key.
+    pub fn get(&self, key: &str) -> Option<&A> {
+        self.moves.get(key)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<A: serde::de::DeserializeOwned> OpeningBook<A> {
+    /// Loads a book from a JSON object that maps position keys to actions.
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json)
+            .map(|moves| OpeningBook { moves })
+            .map_err(|err| err.to_string())
+    }
+}
+
+/// The results of a batch of simulations.
+struct SimResult<P> {
+    // The total number of points available, which is the points for a win in every simulation
+    total_points: u32,
+    // The points each player earned
+    earned_points: HashMap<P, u32>,
+    // The sum of the squares of the points each player earned in each simulation
+    squared_points: HashMap<P, u64>,
+    // The number of simulations that were played to the end and drawn
+    draws: u32
 }
 
-/// Plays `num_sims` games starting from `base_state` with each player performing a random action each turn.
-/// Returns the number of times each player wins one of the simulated games.
-fn simulate<G: Game, R: Rng>(rng: &mut R, base_state: &G, num_sims: u32) -> (u32, HashMap<G::Player, u32>) {
+/// Plays `num_sims` games starting from `base_state` with each player performing an action chosen by
+/// `Game::rollout_action` (by default a random one) each turn. If `max_depth` is set, games that are still going after
+/// that many moves are stopped and scored by `Game::evaluate` instead.
+/// Returns the points earned in the games and how many of them were drawn, and adds the number of times each player won
+/// each region of the board to `region_wins`.
+fn simulate<G: Game, R: Rng>(
+    rng: &mut R, base_state: &G, num_sims: u32, scoring: Scoring, region_wins: &mut Vec<HashMap<G::Player, u32>>,
+    max_depth: Option<u32>
+) -> SimResult<G::Player> {
     let mut points = base_state.get_players().iter()
         .map(|player| (player.clone(), 0))
         .collect::<HashMap<G::Player, u32>>();
+    let mut squared_points = base_state.get_players().iter()
+        .map(|player| (player.clone(), 0))
+        .collect::<HashMap<G::Player, u64>>();
+
+    // A finished game always has the same result, so it only needs to be worked out once
+    if base_state.game_over() {
+        let region_count = base_state.region_count();
+        if region_wins.len() < region_count {
+            region_wins.resize_with(region_count, HashMap::new);
+        }
+        for (region, wins) in region_wins.iter_mut().enumerate().take(region_count) {
+            if let Some(winner) = base_state.region_winner(region) {
+                *wins.entry(winner).or_insert(0) += num_sims;
+            }
+        }
+
+        if let Some(winner) = base_state.winner() {
+            *points.get_mut(&winner).unwrap() += scoring.win * num_sims;
+            *squared_points.get_mut(&winner).unwrap() += scoring.win as u64 * scoring.win as u64 * num_sims as u64;
+        } else {
+            for x in points.values_mut() {
+                *x += scoring.draw * num_sims;
+            }
+            for x in squared_points.values_mut() {
+                *x += scoring.draw as u64 * scoring.draw as u64 * num_sims as u64;
+            }
+        }
+        let draws = if base_state.winner().is_none() { num_sims } else { 0 };
+        return SimResult { total_points: scoring.win * num_sims, earned_points: points, squared_points, draws };
+    }
 
+    // Every simulation starts by resetting the same scratch state, which avoids allocating a new one each time
+    let mut draws = 0;
+    let mut state = base_state.clone();
     for _ in 0..num_sims {
-        let mut state = base_state.clone();
+        state.clone_from(base_state);
 
-        // Make random moves
-        loop {
-            if let Some(action) = state.available_actions().choose(rng).cloned() {
-                state.do_action_mut(&action);
-            } else {
-                // no more possible moves, the game is over
-                break;
+        // Make moves chosen by the game's rollout policy
+        // (the loop ends once there are no more possible moves, i.e. the game is over, or the depth limit is reached)
+        let mut depth = 0;
+        while max_depth.is_none_or(|max_depth| depth < max_depth) {
+            match state.rollout_action(rng) {
+                Some(action) => state.do_action_mut(&action),
+                None => break
+            }
+            depth += 1;
+        }
+
+        // Record who won each region
+        let region_count = state.region_count();
+        if region_wins.len() < region_count {
+            region_wins.resize_with(region_count, HashMap::new);
+        }
+        for (region, wins) in region_wins.iter_mut().enumerate().take(region_count) {
+            if let Some(winner) = state.region_winner(region) {
+                *wins.entry(winner).or_insert(0) += 1;
+            }
+        }
+
+        // Score games that were cut off by how good each player's position looks
+        if !state.game_over() {
+            for (player, earned) in state.evaluate(rng, scoring) {
+                *points.get_mut(&player).unwrap() += earned;
+                *squared_points.get_mut(&player).unwrap() += earned as u64 * earned as u64;
             }
+            continue;
         }
 
         // Update the win count, unless the game tied and there isn't a winner
         if let Some(winner) = state.winner() {
-            // If there was a winner, give them 10 points
-            *points.get_mut(&winner).unwrap() += 10;
+            // If there was a winner, give them the points for a win
+            *points.get_mut(&winner).unwrap() += scoring.win;
+            *squared_points.get_mut(&winner).unwrap() += scoring.win as u64 * scoring.win as u64;
         } else {
-            // Otherwise it was a draw. Give each player one point
+            // Otherwise it was a draw. Give each player the points for a draw
+            draws += 1;
             for x in points.values_mut() {
-                *x += 1;
+                *x += scoring.draw;
+            }
+            for x in squared_points.values_mut() {
+                *x += scoring.draw as u64 * scoring.draw as u64;
+            }
+        }
+    }
+    SimResult { total_points: scoring.win * num_sims, earned_points: points, squared_points, draws }
+}
+
+/// Runs the same simulations as `simulate`, but splits them between `threads` threads, each with its own RNG seeded from
+/// `rng`, and adds up their results. Threads aren't available on wasm, so there it simulates on the calling thread.
+#[cfg(feature = "parallel")]
+fn simulate_parallel<G, R: Rng>(
+    rng: &mut R, base_state: &G, num_sims: u32, scoring: Scoring, region_wins: &mut Vec<HashMap<G::Player, u32>>,
+    max_depth: Option<u32>, threads: usize
+) -> SimResult<G::Player>
+where G: Game + Sync, G::Player: Send {
+    let threads = threads.clamp(1, num_sims.max(1) as usize);
+    if cfg!(target_arch = "wasm32") || threads == 1 {
+        return simulate(rng, base_state, num_sims, scoring, region_wins, max_depth);
+    }
+
+    let seeds = (0..threads).map(|_| rng.gen::<u64>()).collect::<Vec<u64>>();
+    let results = std::thread::scope(|scope| {
+        let handles = seeds.into_iter()
+            .enumerate()
+            .map(|(thread, seed)| {
+                // Spread any leftover simulations over the first few threads
+                let sims = num_sims / threads as u32 + (thread < num_sims as usize % threads) as u32;
+                scope.spawn(move || {
+                    let mut rng = SmallRng::seed_from_u64(seed);
+                    let mut region_wins = Vec::new();
+                    let result = simulate(&mut rng, base_state, sims, scoring, &mut region_wins, max_depth);
+                    (result, region_wins)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect::<Vec<_>>()
+    });
+
+    let mut total = SimResult {
+        total_points: 0,
+        earned_points: HashMap::new(),
+        squared_points: HashMap::new(),
+        draws: 0
+    };
+    for (result, thread_region_wins) in results {
+        total.total_points += result.total_points;
+        total.draws += result.draws;
+        for (player, earned) in result.earned_points {
+            *total.earned_points.entry(player).or_insert(0) += earned;
+        }
+        for (player, squared) in result.squared_points {
+            *total.squared_points.entry(player).or_insert(0) += squared;
+        }
+        if region_wins.len() < thread_region_wins.len() {
+            region_wins.resize_with(thread_region_wins.len(), HashMap::new);
+        }
+        for (wins, thread_wins) in region_wins.iter_mut().zip(thread_region_wins) {
+            for (player, count) in thread_wins {
+                *wins.entry(player).or_insert(0) += count;
             }
         }
     }
-    (10 * num_sims, points)
+    total
+}
+
+/// Searches from `state` using a separate tree on each of `threads` threads, splitting `total_sims` simulations between
+/// them, and combines the statistics of their root children. Returns each action with its total visits and earned points,
+/// sorted so that the most visited action comes first.
+#[cfg(feature = "parallel")]
+pub fn parallel_search<G>(state: G, total_sims: u32, threads: usize) -> Vec<(G::Action, u32, u32)>
+where G: Game + Send + Sync, G::Action: Send {
+    let threads = threads.max(1);
+    let results = std::thread::scope(|scope| {
+        let handles = (0..threads)
+            .map(|thread| {
+                let state = &state;
+                // Spread any leftover simulations over the first few threads
+                let sims = total_sims / threads as u32 + (thread < total_sims as usize % threads) as u32;
+                scope.spawn(move || {
+                    let mut tree = ActionTree::with_seed(state.clone(), thread as u64);
+                    let mut remaining = sims;
+                    while remaining > 0 {
+                        let step_sims = remaining.min(PARALLEL_SIMS_PER_STEP);
+                        tree.do_search_step(step_sims);
+                        remaining -= step_sims;
+                    }
+
+                    let root = tree.nodes.get(tree.root).unwrap();
+                    root.children.iter()
+                        .map(|(action, &child_id)| {
+                            let child = tree.nodes.get(child_id).unwrap();
+                            (action.clone(), child.visits, child.earned_points)
+                        })
+                        .collect::<Vec<(G::Action, u32, u32)>>()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect::<Vec<_>>()
+    });
+
+    // Add up the statistics for each action across all of the trees
+    let mut merged = HashMap::<G::Action, (u32, u32), BuildHasherDefault<DefaultHasher>>::default();
+    for (action, visits, earned_points) in results.into_iter().flatten() {
+        let entry = merged.entry(action).or_insert((0, 0));
+        entry.0 += visits;
+        entry.1 += earned_points;
+    }
+
+    let mut actions = merged.into_iter()
+        .map(|(action, (visits, earned_points))| (action, visits, earned_points))
+        .collect::<Vec<(G::Action, u32, u32)>>();
+    actions.sort_by_key(|&(_, visits, _)| std::cmp::Reverse(visits));
+    actions
+}
+
+/// A summary of the size and shape of a search tree, for monitoring and tuning the search.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TreeStats {
+    /// The number of nodes in the tree, including the root
+    pub node_count: usize,
+    /// The number of simulations run from the root or its descendants
+    pub root_visits: u32,
+    /// The number of moves from the root to the deepest node (0 if the root hasn't been expanded)
+    pub max_depth: usize,
+    /// The number of actions the root has been expanded with
+    pub root_children: usize
+}
+
+impl Display for TreeStats {
+    // Formats the statistics as a JSON object, e.g. for logging them to the console
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f, "{{\"node_count\":{},\"root_visits\":{},\"max_depth\":{},\"root_children\":{}}}",
+            self.node_count, self.root_visits, self.max_depth, self.root_children
+        )
+    }
+}
+
+/// A read-only view of one node in a search tree, as produced by `ActionTree::walk`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct NodeView<'a, A> {
+    /// The node's ID, which stays the same until the node is removed from the tree
+    pub id: usize,
+    /// The ID of the node's parent, or None for the root
+    pub parent: Option<usize>,
+    /// The action that leads to this node from its parent, or None for the root
+    pub action: Option<&'a A>,
+    /// The number of simulations run from this node or its descendants
+    pub visits: u32,
+    /// The points earned in those simulations by the player who moved into this node
+    pub earned_points: u32,
+    /// The points available in those simulations
+    pub total_points: u32,
+    /// The number of moves from the root to this node
+    pub depth: usize
+}
+
+// PUCT score for a node (see Silver et al., "Mastering the game of Go without human knowledge", 2017)
+fn puct_score(exploration: f64, winrate: f64, prior: f64, visits: f64, parent_visits: f64) -> f64 {
+    winrate + exploration * prior * parent_visits.sqrt() / (1.0 + visits)
+}
+
+// Draws a sample from a Gamma(alpha, 1) distribution using Marsaglia and Tsang's method
+fn sample_gamma<R: Rng>(rng: &mut R, alpha: f64) -> f64 {
+    if alpha < 1.0 {
+        // Boost the shape above 1 and scale the result back down
+        let u = 1.0 - rng.gen::<f64>();
+        return sample_gamma(rng, alpha + 1.0) * u.powf(1.0 / alpha);
+    }
+
+    let d = alpha - 1.0 / 3.0;
+    let c = 1.0 / (9.0 * d).sqrt();
+    loop {
+        // Standard normal sample from the Box-Muller transform
+        let (u1, u2) = (1.0 - rng.gen::<f64>(), rng.gen::<f64>());
+        let x = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+
+        let v = (1.0 + c * x).powi(3);
+        if v <= 0.0 {
+            continue;
+        }
+        let u = 1.0 - rng.gen::<f64>();
+        if u.ln() < 0.5 * x * x + d - d * v + d * v.ln() {
+            return d * v;
+        }
+    }
 }
 
+/// A Monte Carlo search tree over the states of a game. With the `serde` feature, trees can be saved and loaded along
+/// with their statistics, but their RNG isn't saved, so a loaded tree is reseeded with the default seed.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(bound(
+    serialize = "G: serde::Serialize, G::Action: serde::Serialize, G::Player: serde::Serialize",
+    deserialize = "G: serde::Deserialize<'de>, G::Action: serde::Deserialize<'de>, G::Player: serde::Deserialize<'de>"
+)))]
 pub struct ActionTree<G: Game> {
+    #[cfg_attr(feature = "serde", serde(skip, default = "default_rng"))]
     rng: SmallRng,
     nodes: Slab<ActionTreeNode<G>>,
-    root: usize
+    root: usize,
+    // The exploration constant used in the UCT score. Higher values spread simulations more evenly between actions.
+    exploration: f64,
+    policy: SelectionPolicy,
+    final_move: FinalMoveSelection,
+    scoring: Scoring,
+    backup: BackupPolicy,
+    // If set, only these actions are considered when expanding the root
+    move_filter: Option<HashSet<G::Action>>,
+    // How many of the simulations run since the root was last changed ended with each player owning each region
+    region_sims: u32,
+    region_wins: Vec<HashMap<G::Player, u32>>,
+    // If set, nodes are no longer expanded once expanding them would make the tree larger than this
+    node_limit: Option<usize>,
+    // Whether results that are certain are worked out from the ends of games and passed up the tree
+    solver: bool,
+    // If set, children that haven't been simulated are scored as the average value of their simulated siblings minus
+    // this, instead of being tried before anything else
+    fpu_reduction: Option<f64>,
+    // If set, simulations stop after this many moves and are scored by `Game::evaluate`
+    rollout_depth: Option<u32>,
+    // Actions to play in known positions instead of searching. Books aren't saved along with the tree.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    book: Option<OpeningBook<G::Action>>
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(bound(
+    serialize = "G: serde::Serialize, G::Action: serde::Serialize, G::Player: serde::Serialize",
+    deserialize = "G: serde::Deserialize<'de>, G::Action: serde::Deserialize<'de>, G::Player: serde::Deserialize<'de>"
+)))]
 struct ActionTreeNode<G: Game> {
     id: usize,
     state: G,
 
+    // The number of simulations run from this node or its descendants
+    visits: u32,
     total_points: u32,
     earned_points: u32,
+    // The sum of the squares of the points earned in each simulation, used to estimate how much the results vary
+    squared_points: u64,
+    // The number of simulations that ended in a draw
+    draws: u32,
+    // Unvisited nodes have infinite scores, which formats like JSON can't represent, so they're saved as None
+    #[cfg_attr(feature = "serde", serde(with = "infinite_as_none"))]
     score: f64,
+    // The prior probability of choosing the action leading to this node, used by PUCT
+    prior: f64,
+    // The value of this node for the player who moved into it, as worked out by `BackupPolicy::Max`
+    value: f64,
+    // Whether the game is over at this node, in which case it never needs to be expanded
+    terminal: bool,
+    // The winner of the game with perfect play from this node (None inside for a draw), once the solver has proven it
+    proven: Option<Option<G::Player>>,
 
     parent: Option<usize>,
-    children: HashMap<G::Action, usize>
+    children: ChildMap<G::Action>
+}
+
+// Saves floats with infinite values as None
+#[cfg(feature = "serde")]
+mod infinite_as_none {
+    use serde::{ Deserialize, Deserializer, Serialize, Serializer };
+
+    pub fn serialize<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        Some(*value).filter(|value| value.is_finite()).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        Ok(Option::<f64>::deserialize(deserializer)?.unwrap_or(f64::INFINITY))
+    }
+}
+
+// The RNG that new trees start with
+fn default_rng() -> SmallRng {
+    SmallRng::seed_from_u64(0)
 }
 
 impl<G: Game> ActionTree<G> {
     pub fn new(state: G) -> Self {
         let mut tree = ActionTree {
-            rng: SmallRng::seed_from_u64(0),
+            rng: default_rng(),
             nodes: Slab::new(),
-            root: 0 // temporarily
+            root: 0, // temporarily
+            exploration: EXPLORATION_FACTOR,
+            policy: SelectionPolicy::Uct,
+            final_move: FinalMoveSelection::MaxWinrate,
+            scoring: Scoring::default(),
+            backup: BackupPolicy::Average,
+            move_filter: None,
+            region_sims: 0,
+            region_wins: Vec::new(),
+            node_limit: None,
+            solver: false,
+            fpu_reduction: None,
+            rollout_depth: None,
+            book: None
         };
         tree.set_root(state);
         tree
     }
 
+    /// Creates a tree whose simulations are driven by an RNG with the given seed. Searches are only reproducible if both
+    /// the seed and the sequence of search steps and actions are the same.
+    pub fn with_seed(state: G, seed: u64) -> Self {
+        let mut tree = ActionTree::new(state);
+        tree.rng = SmallRng::seed_from_u64(seed);
+        tree
+    }
+
+    /// Creates a tree that uses the given exploration constant instead of the default of sqrt(2).
+    pub fn with_exploration(state: G, exploration: f64) -> Self {
+        let mut tree = ActionTree::new(state);
+        tree.exploration = exploration;
+        tree
+    }
+
+    /// Creates a tree that never holds more than `max_nodes` nodes, apart from the root's children, which are always
+    /// added. Once the limit is reached, leaves are simulated again instead of being expanded, until taking an action
+    /// frees up the nodes for the discarded subtrees.
+    pub fn with_node_limit(state: G, max_nodes: usize) -> Self {
+        let mut tree = ActionTree::new(state);
+        tree.node_limit = Some(max_nodes);
+        tree
+    }
+
+    /// Creates a tree that can consult the given opening book with `consult_book`.
+    pub fn with_opening_book(state: G, book: OpeningBook<G::Action>) -> Self {
+        let mut tree = ActionTree::new(state);
+        tree.book = Some(book);
+        tree
+    }
+
+    /// Gets the opening book consulted by `consult_book`, if the tree has one.
+    pub fn opening_book(&self) -> Option<&OpeningBook<G::Action>> {
+        self.book.as_ref()
+    }
+
+    /// Replaces the opening book consulted by `consult_book`.
+    pub fn set_opening_book(&mut self, book: OpeningBook<G::Action>) {
+        self.book = Some(book);
+    }
+
+    /// Changes the exploration constant. Scores are updated with the new value as nodes are visited.
+    pub fn set_exploration(&mut self, exploration: f64) {
+        self.exploration = exploration;
+    }
+
+    /// Gets the exploration constant used in the UCT score.
+    pub fn exploration(&self) -> f64 {
+        self.exploration
+    }
+
+    /// Changes how `get_best_action` picks an action, which is `FinalMoveSelection::MaxWinrate` by default.
+    pub fn set_final_move_selection(&mut self, final_move: FinalMoveSelection) {
+        self.final_move = final_move;
+    }
+
+    /// Changes how many points wins and draws are worth. This should be done before searching, since the statistics
+    /// already in the tree were collected with the old values.
+    pub fn set_scoring(&mut self, scoring: Scoring) {
+        self.scoring = scoring;
+    }
+
+    /// Gets how many points wins and draws are worth.
+    pub fn scoring(&self) -> Scoring {
+        self.scoring
+    }
+
+    /// Changes how nodes are selected for exploration. Scores are updated with the new policy as nodes are visited.
+    pub fn set_policy(&mut self, policy: SelectionPolicy) {
+        self.policy = policy;
+    }
+
+    /// Sets the first-play urgency (FPU) reduction. By default, every action from a node is simulated once before any
+    /// is simulated again, which wastes simulations on hopeless moves when there are many to choose from. With a
+    /// reduction, an action that hasn't been simulated yet is scored as the average value of the actions that have,
+    /// minus the reduction, so it's only tried once the others look worse than that. None goes back to the default.
+    pub fn set_fpu_reduction(&mut self, reduction: Option<f64>) {
+        self.fpu_reduction = reduction;
+    }
+
+    /// Limits simulations to `max_depth` moves, after which the position is scored by `Game::evaluate` instead of being
+    /// played to the end. Shorter simulations are quicker, so more of them can be run, but their results are only as
+    /// good as the evaluation. None plays every simulation to the end, which is the default.
+    pub fn set_rollout_depth(&mut self, max_depth: Option<u32>) {
+        self.rollout_depth = max_depth;
+    }
+
+    /// Changes how simulation results are combined into the value of each node. This should be done before searching,
+    /// since nodes that were already searched only take on the new values as they're visited again.
+    pub fn set_backup_policy(&mut self, backup: BackupPolicy) {
+        self.backup = backup;
+    }
+
+    /// Gets how simulation results are combined into the value of each node.
+    pub fn backup_policy(&self) -> BackupPolicy {
+        self.backup
+    }
+
+    /// Turns the solver on or off. While it's on, the search proves which positions are wins, losses or draws with
+    /// perfect play, starting from the ends of games, and `get_best_action` always picks a proven win if there is one.
+    /// This should be done before searching, since positions that were already searched aren't revisited.
+    pub fn set_solver(&mut self, enabled: bool) {
+        self.solver = enabled;
+    }
+
     fn set_root(&mut self, state: G) {
         let entry = self.nodes.vacant_entry();
         let key = entry.key();
         entry.insert(ActionTreeNode {
             id: key,
-            state: state,
+            terminal: state.game_over(),
+            proven: if state.game_over() { Some(state.winner()) } else { None },
+            state,
 
+            visits: 0,
             total_points: 0,
             earned_points: 0,
-            score: std::f64::INFINITY,
+            squared_points: 0,
+            draws: 0,
+            score: f64::INFINITY,
+            prior: 1.0,
+            value: 0.0,
 
             parent: None,
-            children: HashMap::new()
+            children: ChildMap::default()
         });
         self.root = key;
     }
 
-    /// Selects the node that should be simulated next by following the path with the highest scores
-    fn select(&self) -> usize {
-        let mut current_node_id = self.root;
+    /// Selects the node that should be simulated next by following the path with the highest scores from `start`
+    fn select(&self, start: usize) -> usize {
+        let mut current_node_id = start;
 
         loop {
             let current_node = self.nodes.get(current_node_id).unwrap();
@@ -121,10 +791,34 @@ impl<G: Game> ActionTree<G> {
                 return current_node_id;
             }
 
-            // find the child with maximal score
+            // find the child with maximal score. PUCT scores are worked out here rather than during backpropagation,
+            // since children that haven't been simulated yet still need scores that grow as their parent is visited.
+            let parent_visits = current_node.visits as f64;
+            // With first-play urgency, children that haven't been simulated are assumed to be worth a little less than
+            // their simulated siblings
+            let fpu = self.fpu_reduction.map(|reduction| self.simulated_children_value(current_node) - reduction);
+            let score = |node: &ActionTreeNode<G>| {
+                let score = match (self.policy, fpu) {
+                    (SelectionPolicy::Puct, Some(fpu)) if node.visits == 0 => {
+                        puct_score(self.exploration, fpu, node.prior, 0.0, parent_visits)
+                    }
+                    (_, Some(fpu)) if node.visits == 0 => fpu,
+                    (SelectionPolicy::Puct, _) => {
+                        let value = self.node_value(node);
+                        puct_score(self.exploration, value, node.prior, node.visits as f64, parent_visits)
+                    }
+                    _ => node.score
+                };
+                // A NaN score can't be compared, so it's treated like a node that hasn't been visited
+                if score.is_nan() { f64::INFINITY } else { score }
+            };
+            // Ties, such as between children that haven't been simulated yet, go to the child with the lowest ID, so that
+            // the choice never depends on the order the children are stored in
             let best_child = current_node.children.values()
                 .map(|id| self.nodes.get(*id).unwrap())
-                .max_by(|node_a, node_b| node_a.score.partial_cmp(&node_b.score).unwrap())
+                .max_by(|node_a, node_b| {
+                    score(node_a).partial_cmp(&score(node_b)).unwrap().then_with(|| node_b.id.cmp(&node_a.id))
+                })
                 .unwrap();
             
             // continue with the best child
@@ -132,43 +826,97 @@ impl<G: Game> ActionTree<G> {
         }
     }
 
-    /// Creates a child node of a given node for each action that can be performed on that node's state.
-    /// Returns the ID of one of the children, or the id of this node if no children were created, for use when choosing
-    /// a node to simulate.
+    /// Whether a node has already been given its children.
+    fn is_expanded(&self, node_id: usize) -> bool {
+        self.nodes.get(node_id).is_some_and(|node| !node.children.is_empty())
+    }
+
+    /// Creates the children of a node that hasn't been expanded yet. Returns the ID of one of the new children, or the
+    /// id of this node if no children were created, for use when choosing a node to simulate. Expanding a node a second
+    /// time does nothing, so the statistics of its existing children are never lost.
     fn expand(&mut self, node_id: usize) -> usize {
+        if self.is_expanded(node_id) {
+            return node_id;
+        }
+        self.add_missing_children(node_id)
+    }
+
+    /// Creates a child node of a given node for each action that can be performed on that node's state and doesn't
+    /// already have one. Returns the ID of one of the new children, or the id of this node if no children were created.
+    fn add_missing_children(&mut self, node_id: usize) -> usize {
         // Get information from the node that is being expanded
         // We have to do this in its own block so we can release the borrow on the parent node before inserting the children
-        let parent_state = {
+        let (parent_state, existing_children) = {
             let node = self.nodes.get(node_id).unwrap();
-            node.state.clone()
+            (node.state.clone(), node.children.keys().cloned().collect::<HashSet<G::Action>>())
         };
 
-        // Create a child node for each available action on the parent's state and collect the children's IDs into a HashMap
-        let children = parent_state.available_actions().iter().map(|action| {
-            let entry = self.nodes.vacant_entry();
-            let key = entry.key();
-            entry.insert(ActionTreeNode {
-                id: key,
-                state: *parent_state.do_action(&action),
+        // Only the allowed actions are considered at the root if a move filter is set
+        let filter = if node_id == self.root { self.move_filter.clone() } else { None };
 
-                total_points: 0,
-                earned_points: 0,
-                score: std::f64::INFINITY,
+        // Priors are only needed for PUCT, so other policies skip working them out
+        let uniform_prior = 1.0 / parent_state.available_actions().len() as f64;
+        let priors = if self.policy == SelectionPolicy::Puct { Some(parent_state.action_priors()) } else { None };
 
-                parent: Some(node_id),
-                children: HashMap::new()
-            });
-            (action.clone(), key)
-        }).collect();
+        // Create a child node for each new action on the parent's state and collect the children's IDs
+        let children = parent_state.available_actions().iter()
+            .filter(|action| !existing_children.contains(action))
+            .filter(|action| filter.as_ref().is_none_or(|filter| filter.contains(action)))
+            .map(|action| {
+                let prior = priors.as_ref().and_then(|priors| priors.get(action)).copied().unwrap_or(uniform_prior);
+                let state = parent_state.do_action(action);
+                let entry = self.nodes.vacant_entry();
+                let key = entry.key();
+                entry.insert(ActionTreeNode {
+                    id: key,
+                    terminal: state.game_over(),
+                    proven: if state.game_over() { Some(state.winner()) } else { None },
+                    state,
+
+                    visits: 0,
+                    total_points: 0,
+                    earned_points: 0,
+                    squared_points: 0,
+                    draws: 0,
+                    score: f64::INFINITY,
+                    prior,
+                    value: 0.0,
 
+                    parent: Some(node_id),
+                    children: ChildMap::default()
+                });
+                (action.clone(), key, prior)
+            })
+            .collect::<Vec<(G::Action, usize, f64)>>();
+
+        // Simulate the new child with the highest prior first, or the first new child if they're all the same
+        let first_child = children.iter()
+            .fold(None, |best: Option<(usize, f64)>, &(_, key, prior)| match best {
+                Some((_, best_prior)) if best_prior >= prior => best,
+                _ => Some((key, prior))
+            })
+            .map_or(node_id, |(key, _)| key);
         let node = self.nodes.get_mut(node_id).unwrap();
-        node.children = children;
-        node.children.values().nth(0).copied().unwrap_or(node_id)
+        node.children.extend(children.into_iter().map(|(action, key, _)| (action, key)));
+        first_child
+    }
+
+    /// Checks whether adding children for every action available from a node would keep the tree within its node limit.
+    /// The root can always be expanded, since there would be no actions to choose between otherwise.
+    fn can_expand(&self, node_id: usize) -> bool {
+        match self.node_limit {
+            Some(_) if node_id == self.root => true,
+            Some(limit) => {
+                let new_children = self.nodes.get(node_id).unwrap().state.available_actions().len();
+                self.nodes.len() + new_children <= limit
+            }
+            None => true
+        }
     }
 
     /// Backpropagates the results of a simulation, updating the winrate statistics for all nodes in the path from the
     /// simulated node to the root.
-    fn backpropagate(&mut self, node_id: usize, total_points: u32, earned_points: HashMap<G::Player, u32>) {
+    fn backpropagate(&mut self, node_id: usize, visits: u32, result: SimResult<G::Player>) {
         let mut node = self.nodes.get_mut(node_id).unwrap();
         let mut path = Vec::new();
 
@@ -186,84 +934,637 @@ impl<G: Game> ActionTree<G> {
             }
         }
 
-        // Follow the path from the root back to the leaf, updaing each nodes scores as we go
-        // This is done seperately from the last step so that we can hold onto the parent's simulation count, which is
-        // used in the score function, and the parent's current player, which is who the winrate should be calculated for
+        // Follow the path from the root back to the leaf, updating each node's statistics as we go. Each node earns the
+        // points of the player who moved into it, so with more than two players every ancestor is credited for its own
+        // mover. This is done seperately from the last step so that we can hold onto the parent's current player, which
+        // is who the winrate should be calculated for
         let mut parent_player = node.state.current_player();
         let mut parent_total_points = node.total_points as f64;
+        let mut parent_visits = node.visits as f64;
         for id in path.iter().rev() {
             node = self.nodes.get_mut(*id).unwrap();
+            node.visits += visits;
+            node.total_points += result.total_points;
+            node.earned_points += result.earned_points.get(&parent_player).unwrap_or(&0);
+            node.squared_points += result.squared_points.get(&parent_player).unwrap_or(&0);
+            node.draws += result.draws;
+            parent_player = node.state.current_player();
+        }
+
+        // Max backup values depend on the values of children, so they're worked out from the leaf back up to the root
+        if self.backup == BackupPolicy::Max {
+            for &id in path.iter() {
+                let value = self.max_backup_value(id);
+                self.nodes.get_mut(id).unwrap().value = value;
+            }
+        }
 
-            // Update simulation statistics
-            node.total_points += total_points;
-            node.earned_points += earned_points.get(&parent_player).unwrap_or(&0);
+        // Then update the scores from the root back to the leaf, holding onto the parent's simulation count, which is
+        // used in the score function
+        for id in path.iter().rev() {
+            let (exploitation, exploration) =
+                self.score_parts(self.nodes.get(*id).unwrap(), parent_total_points, parent_visits);
+            node = self.nodes.get_mut(*id).unwrap();
 
             let total_points = node.total_points as f64;
-            let points = node.earned_points as f64;
-            // UCT score (see https://en.wikipedia.org/wiki/Monte_Carlo_tree_search#Exploration_and_exploitation)
-            node.score = (points / total_points) + EXPLORATION_FACTOR * (parent_total_points.ln() / total_points).sqrt();
+            let node_visits = node.visits as f64;
+            node.score = exploitation + exploration;
+            // Simulations that aren't worth any points (such as when wins and draws are both scored as 0) leave the
+            // score undefined, so the node keeps being explored as if it hadn't been visited
+            if node.score.is_nan() {
+                node.score = f64::INFINITY;
+            }
 
-            parent_player = node.state.current_player();
             parent_total_points = total_points;
+            parent_visits = node_visits;
+        }
+    }
+
+    /// Works out the two halves of a node's score under the tree's selection policy: the value of the node for the
+    /// player choosing it, and the bonus for how little it has been explored compared to its parent.
+    fn score_parts(&self, node: &ActionTreeNode<G>, parent_total_points: f64, parent_visits: f64) -> (f64, f64) {
+        let value = self.node_value(node);
+        let total_points = node.total_points as f64;
+        let node_visits = node.visits as f64;
+        let exploration = match self.policy {
+            // UCT score (see https://en.wikipedia.org/wiki/Monte_Carlo_tree_search#Exploration_and_exploitation)
+            SelectionPolicy::Uct => {
+                self.exploration * (parent_total_points.ln() / total_points).sqrt()
+            }
+            // UCB1-Tuned score, which bounds the exploration term by an estimate of the variance of the results
+            // (see Auer et al., "Finite-time Analysis of the Multiarmed Bandit Problem", 2002)
+            SelectionPolicy::Ucb1Tuned => {
+                // Each simulation is worth the points for a win, so rewards are scaled down to be between 0 and 1
+                let mean = node.earned_points as f64 / total_points;
+                let win = self.scoring.win as f64;
+                let mean_square = node.squared_points as f64 / (win * win) / node_visits;
+                let log_ratio = parent_visits.ln() / node_visits;
+                let variance_bound = mean_square - mean * mean + (2.0 * log_ratio).sqrt();
+                (log_ratio * variance_bound.min(0.25)).sqrt()
+            }
+            SelectionPolicy::Puct => {
+                puct_score(self.exploration, 0.0, node.prior, node_visits, parent_visits)
+            }
+        };
+        (value, exploration)
+    }
+
+    // Gets the average value of the children of a node that have been simulated, for the player to move at the node, or
+    // 0 if none of them have been
+    fn simulated_children_value(&self, node: &ActionTreeNode<G>) -> f64 {
+        let (total, count) = node.children.values()
+            .map(|id| self.nodes.get(*id).unwrap())
+            .filter(|child| child.visits > 0)
+            .fold((0.0, 0), |(total, count), child| (total + self.node_value(child), count + 1));
+        if count == 0 { 0.0 } else { total / count as f64 }
+    }
+
+    // Gets the value of a node for the player who moved into it, as set by the backup policy. Nodes that haven't been
+    // simulated are worth 0.
+    fn node_value(&self, node: &ActionTreeNode<G>) -> f64 {
+        match self.backup {
+            BackupPolicy::Max => node.value,
+            BackupPolicy::Average if node.total_points == 0 => 0.0,
+            BackupPolicy::Average => node.earned_points as f64 / node.total_points as f64
+        }
+    }
+
+    // Works out the value of a node for `BackupPolicy::Max`. Once every child has been simulated enough, the player to
+    // move is assumed to pick the best one, which leaves the rest of its value for the player who moved into the node.
+    fn max_backup_value(&self, node_id: usize) -> f64 {
+        let node = self.nodes.get(node_id).unwrap();
+        let children = node.children.values().map(|id| self.nodes.get(*id).unwrap());
+        if !node.children.is_empty() && children.clone().all(|child| child.visits >= MAX_BACKUP_MIN_VISITS) {
+            1.0 - children.map(|child| child.value).fold(f64::NEG_INFINITY, f64::max)
+        } else if node.total_points == 0 {
+            0.0
+        } else {
+            node.earned_points as f64 / node.total_points as f64
+        }
+    }
+
+    /// Works out which ancestors of a newly proven node are now proven as well, stopping at the first one that isn't,
+    /// or that was already proven before.
+    fn propagate_proof(&mut self, node_id: usize) {
+        let mut parent = self.nodes.get(node_id).unwrap().parent;
+        while let Some(id) = parent {
+            let node = self.nodes.get(id).unwrap();
+            if node.proven.is_some() {
+                return;
+            }
+            let proof = self.prove(id);
+            if proof.is_none() {
+                return;
+            }
+            let node = self.nodes.get_mut(id).unwrap();
+            node.proven = proof;
+            parent = node.parent;
+        }
+    }
+
+    /// Tries to prove the result of a node from its children. The player to move wins if any action wins for them, and
+    /// otherwise the node is only proven once every action is, in which case they settle for a draw if they can.
+    fn prove(&self, node_id: usize) -> Option<Option<G::Player>> {
+        let node = self.nodes.get(node_id).unwrap();
+        let player = node.state.current_player();
+        let proofs = node.children.values()
+            .map(|id| self.nodes.get(*id).unwrap().proven.clone())
+            .collect::<Vec<Option<Option<G::Player>>>>();
+
+        if proofs.iter().any(|proof| proof.as_ref() == Some(&Some(player.clone()))) {
+            return Some(Some(player));
+        }
+        if proofs.len() < node.state.available_actions().len() || proofs.iter().any(Option::is_none) {
+            return None;
+        }
+        if proofs.iter().any(|proof| proof == &Some(None)) {
+            return Some(None);
+        }
+        // Every action lets another player win. With more than two players, the result is only certain if it's the
+        // same player every time.
+        let winner = proofs[0].clone();
+        if proofs.iter().all(|proof| proof == &winner) { winner } else { None }
+    }
+
+    /// Gets the action the opening book has for the root position, if there is one and it can be played. This doesn't
+    /// need any simulations.
+    pub fn consult_book(&self) -> Option<G::Action> {
+        let state = &self.nodes.get(self.root).unwrap().state;
+        let action = self.book.as_ref()?.get(&state.position_key()?)?;
+        let allowed = self.move_filter.as_ref().is_none_or(|filter| filter.contains(action));
+        if allowed && state.available_actions().contains(action) { Some(action.clone()) } else { None }
+    }
+
+    /// Gets the result of the game from the root with perfect play, if the solver has proven it.
+    pub fn proven_result(&self) -> Option<ProvenOutcome> {
+        let root = self.nodes.get(self.root).unwrap();
+        root.proven.as_ref().map(|winner| match winner {
+            Some(winner) if *winner == root.state.current_player() => ProvenOutcome::Win,
+            Some(_) => ProvenOutcome::Loss,
+            None => ProvenOutcome::Draw
+        })
+    }
+
+    /// Expands the root and gives its children a head start before searching, such as the number of times each action
+    /// was played in earlier games. Each seeded visit counts as a win for the player choosing the action, so seeded
+    /// actions are searched first, and the more visits they're given, the longer it takes for simulations to outweigh
+    /// them. Actions that can't be played at the root are ignored. Points are counted in a `u32`, so this fails without
+    /// seeding anything if the root's points plus `scoring.win` for every seeded visit wouldn't fit in one, which at
+    /// the default scoring is after about 429 million visits.
+    pub fn seed_children(&mut self, priors: HashMap<G::Action, u32>) -> Result<(), TooManyVisits> {
+        self.expand(self.root);
+
+        // A child never has more points than the root, so if the root's total fits then so do all of the children's
+        let win = self.scoring.win;
+        let root = self.nodes.get(self.root).unwrap();
+        priors.iter()
+            .filter(|(action, _)| root.children.contains_key(action))
+            .try_fold(0u32, |seeded, (_, &visits)| seeded.checked_add(visits))
+            .and_then(|seeded| seeded.checked_mul(win))
+            .and_then(|points| root.total_points.checked_add(points))
+            .ok_or(TooManyVisits)?;
+
+        let mut seeded = 0;
+        for (action, visits) in priors {
+            let child_id = match self.nodes.get(self.root).unwrap().children.get(&action) {
+                Some(&child_id) => child_id,
+                None => continue
+            };
+            let child = self.nodes.get_mut(child_id).unwrap();
+            child.visits += visits;
+            child.total_points += visits * win;
+            child.earned_points += visits * win;
+            child.squared_points += visits as u64 * (win as u64 * win as u64);
+            seeded += visits;
+        }
+
+        // The seeded visits pass through the root as well, as losses for the player who moved into it
+        let root = self.nodes.get_mut(self.root).unwrap();
+        root.visits += seeded;
+        root.total_points += seeded * win;
+
+        // Every child's score depends on the root's statistics, so they're all worked out again
+        let child_ids = root.children.values().copied().collect::<Vec<usize>>();
+        if self.backup == BackupPolicy::Max {
+            for &id in child_ids.iter().chain(std::iter::once(&self.root)) {
+                let value = self.max_backup_value(id);
+                self.nodes.get_mut(id).unwrap().value = value;
+            }
+        }
+        let root = self.nodes.get(self.root).unwrap();
+        let (parent_total_points, parent_visits) = (root.total_points as f64, root.visits as f64);
+        for id in child_ids {
+            let node = self.nodes.get(id).unwrap();
+            if node.visits == 0 {
+                continue;
+            }
+            let (exploitation, exploration) = self.score_parts(node, parent_total_points, parent_visits);
+            let score = exploitation + exploration;
+            self.nodes.get_mut(id).unwrap().score = if score.is_nan() { f64::INFINITY } else { score };
         }
+        Ok(())
     }
 
     /// Performs a single step of the Monte Carlo tree search algorithm.
     /// (See https://en.wikipedia.org/wiki/Monte_Carlo_tree_search#Principle_of_operation)
     pub fn do_search_step(&mut self, num_sims: u32) {
+        self.search_step_from(self.root, num_sims);
+    }
+
+    /// Performs `iterations` search steps of `sims_each` simulations each. Every step adds statistics for one more node,
+    /// so spreading the same number of simulations over more iterations grows the tree faster, while running more
+    /// simulations in each gives every new node a more reliable estimate.
+    pub fn do_search_iterations(&mut self, iterations: u32, sims_each: u32) {
+        for _ in 0..iterations {
+            self.do_search_step(sims_each);
+        }
+    }
+
+    /// Runs `sims` simulations split into about 100 search steps, so that the tree grows even when there are only a few
+    /// simulations, while large searches still run up to 100 simulations in each step.
+    pub fn do_search_sims(&mut self, sims: u32) {
+        let step = (sims / 100).clamp(1, 100);
+        self.do_search_iterations(sims / step, step);
+        let rest = sims % step;
+        if rest > 0 {
+            self.do_search_step(rest);
+        }
+    }
+
+    /// Performs a search step for pondering, while waiting for the opponent to choose an action at the root. Since any
+    /// of their actions might be played, each step searches below the least simulated one instead of the one that looks
+    /// best for them. Calling `do_action` once their action is known keeps the statistics gathered for it.
+    pub fn ponder_step(&mut self, num_sims: u32) {
+        if !self.is_expanded(self.root) {
+            if self.nodes.get(self.root).unwrap().total_points == 0 || !self.can_expand(self.root) {
+                return self.do_search_step(num_sims);
+            }
+            self.expand(self.root);
+        }
+
+        let root = self.nodes.get(self.root).unwrap();
+        let start = root.children.values()
+            .copied()
+            .min_by_key(|id| self.nodes.get(*id).unwrap().visits)
+            .unwrap_or(self.root);
+        self.search_step_from(start, num_sims);
+    }
+
+    fn search_step_from(&mut self, start: usize, num_sims: u32) {
+        let max_depth = self.rollout_depth;
+        self.search_step_with(start, num_sims, |rng, state, num_sims, scoring, region_wins| {
+            simulate(rng, state, num_sims, scoring, region_wins, max_depth)
+        });
+    }
+
+    // Performs a search step from `start`, running the simulations with the given function, which takes the same
+    // arguments as `simulate`
+    fn search_step_with<F>(&mut self, start: usize, num_sims: u32, simulate: F)
+    where F: FnOnce(&mut SmallRng, &G, u32, Scoring, &mut Vec<HashMap<G::Player, u32>>) -> SimResult<G::Player> {
         // Select a node to simulate
-        let mut node_to_sim = self.select();
+        let mut node_to_sim = self.select(start);
         
-        // If this node has already been simulated, then we should expand it and simulate one of the children instead
+        // If this node has already been simulated, then we should expand it and simulate one of the children instead.
+        // Finished games have no children, so they're just simulated again, which is cheap
         let should_expand = {
             if let Some(node) = self.nodes.get(node_to_sim) {
-                node.total_points > 0
+                node.total_points > 0 && !node.terminal && !self.is_expanded(node_to_sim)
             } else {
                 false
             }
         };
 
-        // Expand the node if necessary
-        if should_expand {
+        // Expand the node if necessary and if its children would fit within the node limit
+        if should_expand && self.can_expand(node_to_sim) {
             node_to_sim = self.expand(node_to_sim);
         }
 
         if let Some(node) = self.nodes.get(node_to_sim) {
             // Do the simulation
-            let (total_points, wins) = simulate(&mut self.rng, &node.state, num_sims);
+            let result = simulate(&mut self.rng, &node.state, num_sims, self.scoring, &mut self.region_wins);
+            self.region_sims += num_sims;
 
             // Backpropagate the simulation results
-            self.backpropagate(node_to_sim, total_points, wins);
+            self.backpropagate(node_to_sim, num_sims, result);
+
+            // Reaching the end of a game might settle the result of the positions leading to it
+            if self.solver && self.nodes.get(node_to_sim).unwrap().terminal {
+                self.propagate_proof(node_to_sim);
+            }
         }
 
     }
 
-    /// Gets the action that provides the best estimated winrate for the current player.
-    pub fn get_best_action(&self) -> Option<(&G::Action, usize)> {
-        let root = self.nodes.get(self.root).unwrap();
-
-        let mut best_winrate = 0.0;
-        let mut best_action = None;
-        for (action, child_id) in root.children.iter() {
-            let child = self.nodes.get(*child_id).unwrap();
-            let winrate = child.earned_points as f64 / child.total_points as f64;
-            if winrate > best_winrate {
-                best_winrate = winrate;
-                best_action = Some((action, *child_id));
+    /// Runs search steps of `sims_per_step` simulations each until `budget` has elapsed, always running at least one.
+    /// Returns the number of steps that were run.
+    pub fn search_for(&mut self, budget: Duration, sims_per_step: u32) -> u32 {
+        let stopwatch = Stopwatch::start();
+        let mut steps = 0;
+        loop {
+            self.do_search_step(sims_per_step);
+            steps += 1;
+            if stopwatch.elapsed() >= budget {
+                return steps;
             }
         }
-
-        best_action
     }
 
-    /// Removes any nodes that can no longer be reached from the root node
-    fn collect_garbage(&mut self) {
+    /// Runs search steps of `step` simulations each until every action at the root has been tried and the most visited
+    /// one has more than `confidence` of their visits, or until `max_sims` simulations have been run. Returns the
+    /// number of simulations that were run.
+    pub fn search_until_confident(&mut self, step: u32, confidence: f64, max_sims: u32) -> u32 {
+        let step = step.max(1);
+        let mut sims = 0;
+        while sims < max_sims {
+            let step_sims = step.min(max_sims - sims);
+            self.do_search_step(step_sims);
+            sims += step_sims;
+
+            // The share of visits only means something once every action has been tried
+            let root = self.nodes.get(self.root).unwrap();
+            let child_visits = root.children.values().map(|&id| self.nodes.get(id).unwrap().visits);
+            let (fewest_visits, most_visits, total_visits) = child_visits.fold(
+                (u32::MAX, 0, 0),
+                |(fewest, most, total), visits| (fewest.min(visits), most.max(visits), total + visits)
+            );
+            if fewest_visits > 0 && most_visits as f64 / total_visits as f64 > confidence {
+                break;
+            }
+        }
+        sims
+    }
+
+    /// Runs `total_sims` search steps of one simulation each, calling `callback` with the number of simulations run so
+    /// far and the current best action (see `get_best_action`) after every `every` simulations and at the end, so that
+    /// long searches can report their progress.
+    pub fn search_with_callback<F>(&mut self, total_sims: u32, every: u32, mut callback: F)
+    where F: FnMut(u32, Option<&G::Action>) {
+        let every = every.max(1);
+        for sims in 1..=total_sims {
+            self.do_search_step(1);
+            if sims % every == 0 || sims == total_sims {
+                callback(sims, self.get_best_action().map(|(action, _)| action));
+            }
+        }
+    }
+
+    /// Gets the action that has been simulated the most, which is less sensitive to lucky simulations than the action
+    /// with the best winrate.
+    pub fn most_visited_action(&self) -> Option<(&G::Action, usize)> {
+        self.most_visited_child(self.root)
+    }
+
+    /// Picks a random action at the root with probability proportional to its visit count raised to the power of
+    /// `1 / tau`. As `tau` approaches 0 this becomes the most visited action, and with `tau` at 1 actions are picked in
+    /// proportion to their visits, which makes for weaker, less predictable play. Returns None if the root hasn't been
+    /// expanded.
+    pub fn sample_action(&mut self, tau: f64) -> Option<G::Action> {
+        let root = self.nodes.get(self.root).unwrap();
+        if tau.is_nan() || tau <= 0.0 {
+            return self.most_visited_action().map(|(action, _)| action.clone());
+        }
+
+        // Weights are worked out relative to the most visited action, so that small temperatures don't overflow
+        let children = root.children.iter()
+            .map(|(action, id)| (action, self.nodes.get(*id).unwrap().visits))
+            .collect::<Vec<(&G::Action, u32)>>();
+        let max_visits = children.iter().map(|&(_, visits)| visits).max()?;
+        if max_visits == 0 {
+            return children.choose(&mut self.rng).map(|(action, _)| (*action).clone());
+        }
+        let weights = children.iter()
+            .map(|&(_, visits)| ((visits as f64).ln() - (max_visits as f64).ln()) / tau)
+            .map(f64::exp)
+            .collect::<Vec<f64>>();
+
+        let mut target = self.rng.gen::<f64>() * weights.iter().sum::<f64>();
+        for (&(action, _), weight) in children.iter().zip(weights) {
+            if target < weight {
+                return Some(action.clone());
+            }
+            target -= weight;
+        }
+        // Rounding can leave a little of the target over, in which case the last action with any weight is picked
+        children.iter().rev().find(|&&(_, visits)| visits > 0).map(|(action, _)| (*action).clone())
+    }
+
+    /// Picks the opening move uniformly at random from the `top_k` most visited actions, so that games started by the AI
+    /// don't all open the same way. Strong openings are usually close in value, so this costs little. Once the game has
+    /// started (see `Game::is_initial_state`), this is the same as `get_best_action`.
+    pub fn randomize_first_move(&mut self, top_k: usize) -> Option<G::Action> {
+        let root = self.nodes.get(self.root).unwrap();
+        if !root.state.is_initial_state() {
+            return self.get_best_action().map(|(action, _)| action.clone());
+        }
+
+        let mut children = root.children.iter()
+            .map(|(action, id)| (action, self.nodes.get(*id).unwrap().visits))
+            .filter(|&(_, visits)| visits > 0)
+            .collect::<Vec<(&G::Action, u32)>>();
+        children.sort_by_key(|&(_, visits)| std::cmp::Reverse(visits));
+        children.truncate(top_k.max(1));
+        match children.choose(&mut self.rng) {
+            Some((action, _)) => Some((*action).clone()),
+            None => self.get_best_action().map(|(action, _)| action.clone())
+        }
+    }
+
+    fn most_visited_child(&self, node_id: usize) -> Option<(&G::Action, usize)> {
+        let node = self.nodes.get(node_id).unwrap();
+
+        node.children.iter()
+            .max_by_key(|(_, child_id)| self.nodes.get(**child_id).unwrap().visits)
+            .map(|(action, child_id)| (action, *child_id))
+    }
+
+    /// Gets the line of play the search currently expects, by following the most visited child from the root until
+    /// reaching a node that hasn't been expanded or whose children haven't been simulated.
+    pub fn principal_variation(&self) -> Vec<G::Action> {
+        let mut line = Vec::new();
+        let mut node_id = self.root;
+        while let Some((action, child_id)) = self.most_visited_child(node_id) {
+            if self.nodes.get(child_id).unwrap().visits == 0 {
+                break;
+            }
+            line.push(action.clone());
+            node_id = child_id;
+        }
+        line
+    }
+
+    /// Gets the action the search recommends for the current player, chosen as set by `set_final_move_selection`.
+    pub fn get_best_action(&self) -> Option<(&G::Action, usize)> {
+        let root = self.nodes.get(self.root).unwrap();
+
+        // With the solver on, proven wins come before everything else and proven losses come last
+        let player = root.state.current_player();
+        let proof_rank = |child_id: usize| match &self.nodes.get(child_id).unwrap().proven {
+            Some(Some(winner)) if self.solver && *winner == player => 2,
+            Some(Some(_)) if self.solver => 0,
+            _ => 1
+        };
+
+        // Start below any possible value so that an action is always chosen, even if every action always loses
+        let mut best_value = (0, f64::NEG_INFINITY);
+        let mut best_action = None;
+        for (action, child_id) in root.children.iter() {
+            let value = (proof_rank(*child_id), self.final_move_value(*child_id));
+            if value > best_value {
+                best_value = value;
+                best_action = Some((action, *child_id));
+            }
+        }
+
+        best_action
+    }
+
+    /// Rates a child of the root for `get_best_action`, where higher is better.
+    fn final_move_value(&self, node_id: usize) -> f64 {
+        let visits = self.nodes.get(node_id).unwrap().visits;
+        match self.final_move {
+            FinalMoveSelection::MostVisited => visits as f64,
+            FinalMoveSelection::MaxWinrate => self.node_winrate(node_id),
+            FinalMoveSelection::MaxLowerBound if visits == 0 => f64::MIN,
+            FinalMoveSelection::MaxLowerBound => {
+                self.node_winrate(node_id) - (LOWER_BOUND_FACTOR / visits as f64).sqrt()
+            }
+        }
+    }
+
+    /// Returns the value of a node for the player who chose it, which is the fraction of its simulation points that
+    /// they earned unless `BackupPolicy::Max` is used, or 0 if it hasn't been simulated.
+    fn node_winrate(&self, node_id: usize) -> f64 {
+        self.node_value(self.nodes.get(node_id).unwrap())
+    }
+
+    /// Lists the actions that have been expanded at the root along with their node IDs, sorted from the best to the
+    /// worst estimated winrate.
+    pub fn ranked_actions(&self) -> Vec<(&G::Action, usize)> {
+        let root = self.nodes.get(self.root).unwrap();
+
+        let mut actions = root.children.iter()
+            .map(|(action, child_id)| (action, *child_id))
+            .collect::<Vec<(&G::Action, usize)>>();
+        actions.sort_by(|(_, a), (_, b)| self.node_winrate(*b).partial_cmp(&self.node_winrate(*a)).unwrap());
+        actions
+    }
+
+    /// Gets the action with the second best estimated winrate, along with how much lower its winrate is than the best
+    /// action's.
+    pub fn runner_up(&self) -> Option<(&G::Action, usize, f64)> {
+        let ranked = self.ranked_actions();
+        let (_, best_id) = *ranked.first()?;
+        let (action, child_id) = *ranked.get(1)?;
+        Some((action, child_id, self.node_winrate(best_id) - self.node_winrate(child_id)))
+    }
+
+    /// Estimates the probability that the player to move at the root wins the game, as the visit-weighted average
+    /// winrate of the root's children. Falls back to the root's own statistics if it hasn't been expanded, and returns
+    /// None if nothing has been simulated yet.
+    pub fn root_win_probability(&self) -> Option<f64> {
+        let root = self.nodes.get(self.root).unwrap();
+
+        let (earned_points, total_points) = if root.children.is_empty() {
+            (root.earned_points, root.total_points)
+        } else {
+            root.children.values()
+                .map(|id| self.nodes.get(*id).unwrap())
+                .fold((0, 0), |(earned, total), child| (earned + child.earned_points, total + child.total_points))
+        };
+
+        if total_points == 0 {
+            None
+        } else {
+            Some(earned_points as f64 / total_points as f64)
+        }
+    }
+
+    /// Checks whether the current player is losing badly enough to resign: either the solver has proven a loss, or
+    /// their estimated chance of winning is below `threshold` after enough simulations to be sure of it. Always false
+    /// once the game is over.
+    pub fn should_resign(&self, threshold: f64) -> bool {
+        if self.is_game_over() {
+            return false;
+        }
+        self.proven_result() == Some(ProvenOutcome::Loss)
+            || self.settled_win_probability().is_some_and(|p| p < threshold)
+    }
+
+    /// Checks whether the current player can't expect better than a draw, and might as well offer one: either the
+    /// solver has proven a draw, or their estimated winrate is within `margin` of what a draw earns them (see
+    /// `Scoring`) after enough simulations to be sure of it. Always false once the game is over.
+    pub fn should_claim_draw(&self, margin: f64) -> bool {
+        if self.is_game_over() {
+            return false;
+        }
+        let draw_score = self.scoring.draw as f64 / self.scoring.win as f64;
+        self.proven_result() == Some(ProvenOutcome::Draw)
+            || self.settled_win_probability().is_some_and(|p| (p - draw_score).abs() < margin)
+    }
+
+    // Gets `root_win_probability`, unless too few simulations have been run from the root to rely on it
+    fn settled_win_probability(&self) -> Option<f64> {
+        let visits = self.nodes.get(self.root).unwrap().visits;
+        self.root_win_probability().filter(|_| visits >= MIN_VISITS_TO_CONCEDE)
+    }
+
+    /// Estimates how likely a player is to win each region of the board (see `Game::region_count`), from the
+    /// simulations run since the root was last changed.
+    pub fn region_win_probabilities(&self, player: &G::Player) -> Vec<f64> {
+        self.region_wins.iter()
+            .map(|wins| {
+                if self.region_sims == 0 {
+                    0.0
+                } else {
+                    *wins.get(player).unwrap_or(&0) as f64 / self.region_sims as f64
+                }
+            })
+            .collect()
+    }
+
+    /// Estimates the root player's chance of winning like `root_win_probability`, but pulls the estimate towards 0.5
+    /// while the root has few visits so that it doesn't jump around early in the search. `prior_weight` is how many
+    /// visits the even prior counts for.
+    pub fn smoothed_eval(&self, prior_weight: f64) -> f64 {
+        let visits = self.nodes.get(self.root).unwrap().visits as f64;
+        let raw = self.root_win_probability().unwrap_or(0.5);
+        let weight = if prior_weight + visits > 0.0 { prior_weight / (prior_weight + visits) } else { 0.0 };
+        weight * 0.5 + (1.0 - weight) * raw
+    }
+
+    /// Formats the statistics of each of the root's children as CSV, one row per child from the best to the worst
+    /// estimated winrate, for analysis outside of the program.
+    pub fn stats_csv(&self) -> String where G::Action: Display {
+        let mut csv = String::from("action,visits,wins,winrate,score\n");
+        for (action, child_id) in self.ranked_actions() {
+            let child = self.nodes.get(child_id).unwrap();
+            writeln!(
+                csv, "{},{},{},{},{}",
+                action, child.visits, child.earned_points, self.node_winrate(child_id), child.score
+            ).unwrap();
+        }
+        csv
+    }
+
+    /// Removes a node and all of its descendants, except for the subtree under `keep`.
+    fn remove_subtree(&mut self, node_id: usize, keep: usize) {
+        let mut openset = vec![node_id];
+        while let Some(id) = openset.pop() {
+            if id == keep {
+                continue;
+            }
+            let node = self.nodes.remove(id);
+            openset.extend(node.children.values());
+        }
+    }
+
+    /// Removes any nodes that can no longer be reached from the root node
+    fn collect_garbage(&mut self) {
         // Mark all of the nodes that can be reached from the root
         let mut marked_nodes = HashSet::new();
         let mut openset = vec![self.root];
-        while !openset.is_empty() {
-            // Take a node from the openset & mark it
-            let id = openset.pop().unwrap();
+        while let Some(id) = openset.pop() {
+            // Mark the node taken from the openset
             marked_nodes.insert(id);
             // Add all children of that node to the openset
             let node = self.nodes.get(id).unwrap();
@@ -282,8 +1583,46 @@ impl<G: Game> ActionTree<G> {
         }
     }
 
-    pub fn do_action(&mut self, action: &G::Action) {
+    /// Restricts the search to the given actions at the root, discarding any statistics already gathered for other
+    /// actions. The filter only applies to the current position and is cleared when an action is performed.
+    pub fn set_move_filter(&mut self, allowed: &[G::Action]) {
+        let filter = allowed.iter().cloned().collect::<HashSet<G::Action>>();
+        let root = self.nodes.get_mut(self.root).unwrap();
+        root.children.retain(|action, _| filter.contains(action));
+        // The root's result might have depended on one of the actions that was just removed
+        if !root.terminal {
+            root.proven = None;
+        }
+        self.move_filter = Some(filter);
+        self.collect_garbage();
+    }
+
+    /// Removes the move filter, letting the search consider every action at the root again.
+    pub fn clear_move_filter(&mut self) {
+        if self.move_filter.take().is_some() {
+            // Add back the children that the filter kept out, if the root has already been expanded
+            if self.is_expanded(self.root) {
+                self.add_missing_children(self.root);
+            }
+        }
+    }
+
+    /// Moves the root to the state after `action`, keeping the statistics of the subtree under it. Fails without
+    /// changing the tree if the action isn't legal at the root.
+    pub fn do_action(&mut self, action: &G::Action) -> Result<(), IllegalAction> {
+        // Children are only made for legal actions, so only actions that haven't been expanded need to be checked
+        let root = self.nodes.get(self.root).unwrap();
+        if !root.children.contains_key(action) && !root.state.available_actions().contains(action) {
+            return Err(IllegalAction);
+        }
+
+        // The move filter and region statistics were only meant for the old root
+        self.move_filter = None;
+        self.region_sims = 0;
+        self.region_wins.clear();
+
         // Find the ID of the new root among the current root's children
+        let old_root = self.root;
         let root = self.nodes.get(self.root).unwrap();
         if let Some(new_root_id) = root.children.get(action) {
             // Set the tree's root to the new root
@@ -294,20 +1633,176 @@ impl<G: Game> ActionTree<G> {
         } else {
             // A node for this child doesn't exist yet, so we should make one
             let next_state = root.state.do_action(action);
-            self.set_root(*next_state);
+            self.set_root(next_state);
+        }
+        // Only the old root and the subtrees of the actions that weren't taken have become unreachable, so they can be
+        // removed without scanning the rest of the tree
+        self.remove_subtree(old_root, self.root);
+        Ok(())
+    }
+
+    /// Throws away the whole tree and starts searching from a new state, keeping the tree's settings and RNG.
+    pub fn reset_to(&mut self, state: G) {
+        self.move_filter = None;
+        self.region_sims = 0;
+        self.region_wins.clear();
+        self.nodes.clear();
+        self.set_root(state);
+    }
+
+    /// Restarts the RNG that drives the simulations from the given seed.
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = SmallRng::seed_from_u64(seed);
+    }
+
+    /// Summarizes the size and shape of the tree.
+    pub fn stats(&self) -> TreeStats {
+        let root = self.nodes.get(self.root).unwrap();
+
+        // Walk the whole tree to find its deepest node
+        let mut max_depth = 0;
+        let mut stack = vec![(self.root, 0)];
+        while let Some((node_id, depth)) = stack.pop() {
+            max_depth = max_depth.max(depth);
+            let node = self.nodes.get(node_id).unwrap();
+            stack.extend(node.children.values().map(|&child_id| (child_id, depth + 1)));
+        }
+
+        TreeStats {
+            node_count: self.nodes.len(),
+            root_visits: root.visits,
+            max_depth,
+            root_children: root.children.len()
+        }
+    }
+
+    /// Works out the branching factor a tree with every node expanded would need to have as many nodes as this one in
+    /// as many levels, i.e. the `b` for which `1 + b + b^2 + ... + b^max_depth` is the node count. Lower values mean
+    /// the search is spending its nodes on deeper lines instead of more alternatives. Returns 0 before the root has been
+    /// expanded.
+    pub fn effective_branching(&self) -> f64 {
+        let stats = self.stats();
+        if stats.max_depth == 0 {
+            return 0.0;
+        }
+
+        let node_count = stats.node_count as f64;
+        let nodes_with_branching = |b: f64| (0..=stats.max_depth as i32).map(|depth| b.powi(depth)).sum::<f64>();
+        // The node count only grows with the branching factor, so it can be found by bisection
+        let (mut low, mut high) = (0.0, node_count);
+        for _ in 0..64 {
+            let mid = (low + high) / 2.0;
+            if nodes_with_branching(mid) < node_count {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        (low + high) / 2.0
+    }
+
+    /// Gets the average number of simulations run through the root per node in the tree. Higher values mean each node's
+    /// statistics are more reliable, while lower values mean the tree is growing faster.
+    pub fn simulations_per_node(&self) -> f64 {
+        self.nodes.get(self.root).unwrap().visits as f64 / self.nodes.len() as f64
+    }
+
+    /// Visits the nodes of the tree depth first, starting from the root, for tools that display the search. Nodes more
+    /// than `max_depth` moves below the root are skipped if it's given.
+    pub fn walk(&self, max_depth: Option<usize>) -> impl Iterator<Item = NodeView<'_, G::Action>> {
+        let mut stack = vec![(self.root, None, 0)];
+        std::iter::from_fn(move || {
+            let (id, action, depth) = stack.pop()?;
+            let node = self.nodes.get(id).unwrap();
+            if max_depth.is_none_or(|max_depth| depth < max_depth) {
+                stack.extend(node.children.iter().map(|(action, &child_id)| (child_id, Some(action), depth + 1)));
+            }
+
+            Some(NodeView {
+                id,
+                parent: node.parent,
+                action,
+                visits: node.visits,
+                earned_points: node.earned_points,
+                total_points: node.total_points,
+                depth
+            })
+        })
+    }
+
+    /// Mixes Dirichlet(`alpha`) noise into the priors of the root's actions, as `(1 - epsilon) * prior + epsilon *
+    /// noise`, so that the search sometimes explores moves it wouldn't otherwise consider. Expands the root if it
+    /// hasn't been expanded yet, and does nothing unless `alpha` is positive. Priors are only used by
+    /// `SelectionPolicy::Puct`, and the noise only lasts until the root changes.
+    pub fn add_root_noise(&mut self, epsilon: f64, alpha: f64) {
+        // The Dirichlet distribution is only defined for positive values of alpha
+        if alpha.is_nan() || alpha <= 0.0 {
+            return;
+        }
+
+        let root_id = self.root;
+        self.expand(root_id);
+
+        let children = self.nodes.get(root_id).unwrap().children.values().copied().collect::<Vec<usize>>();
+        let noise = children.iter().map(|_| sample_gamma(&mut self.rng, alpha)).collect::<Vec<f64>>();
+        let total = noise.iter().sum::<f64>();
+        if total <= 0.0 {
+            return;
+        }
+
+        for (child_id, sample) in children.into_iter().zip(noise) {
+            let child = self.nodes.get_mut(child_id).unwrap();
+            child.prior = (1.0 - epsilon) * child.prior + epsilon * sample / total;
         }
-        // This will make some nodes unreachable, so remove them
-        self.collect_garbage();
+    }
+
+    /// Gets the number of nodes currently stored in the tree
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
     }
 
     pub fn get_node_earned_points(&self, node: usize) -> u32 {
         self.nodes.get(node).unwrap().earned_points
     }
 
+    pub fn get_node_visits(&self, node: usize) -> u32 {
+        self.nodes.get(node).unwrap().visits
+    }
+
     pub fn get_node_total_points(&self, node: usize) -> u32 {
         self.nodes.get(node).unwrap().total_points
     }
 
+    /// Gets how many of the simulations run through a node ended in a draw. Games stopped early by the rollout depth
+    /// aren't counted, even if they're scored as drawn.
+    pub fn get_node_draws(&self, node: usize) -> u32 {
+        self.nodes.get(node).unwrap().draws
+    }
+
+    /// Gets the points earned and the total points available in the simulations through the given action at the root,
+    /// or None if the root hasn't been expanded with that action.
+    pub fn child_stats(&self, action: &G::Action) -> Option<(u32, u32)> {
+        let child_id = *self.nodes.get(self.root).unwrap().children.get(action)?;
+        let child = self.nodes.get(child_id).unwrap();
+        Some((child.earned_points, child.total_points))
+    }
+
+    /// Splits the score of the given action at the root into the winrate it has earned and the bonus it gets for having
+    /// been explored less than the other actions, worked out from the current statistics of the root. Returns None if
+    /// the action hasn't been expanded at the root or hasn't been simulated yet.
+    pub fn score_components(&self, action: &G::Action) -> Option<(f64, f64)> {
+        let root = self.nodes.get(self.root).unwrap();
+        let child = self.nodes.get(*root.children.get(action)?).unwrap();
+        if child.visits == 0 {
+            return None;
+        }
+        Some(self.score_parts(child, root.total_points as f64, root.visits as f64))
+    }
+
+    pub fn root_state(&self) -> &G {
+        &self.nodes.get(self.root).unwrap().state
+    }
+
     pub fn is_game_over(&self) -> bool {
         self.nodes.get(self.root).unwrap().state.game_over()
     }
@@ -315,4 +1810,1248 @@ impl<G: Game> ActionTree<G> {
     pub fn current_player(&self) -> G::Player {
         self.nodes.get(self.root).unwrap().state.current_player()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(feature = "parallel")]
+impl<G> ActionTree<G> where G: Game + Sync, G::Player: Send {
+    /// Performs a search step like `do_search_step`, but splits its simulations between `threads` threads. Only the
+    /// simulations run in parallel, so the tree is the same as with single-threaded steps. On wasm, this is the same
+    /// as `do_search_step`.
+    pub fn do_parallel_search_step(&mut self, num_sims: u32, threads: usize) {
+        let max_depth = self.rollout_depth;
+        self.search_step_with(self.root, num_sims, |rng, state, num_sims, scoring, region_wins| {
+            simulate_parallel(rng, state, num_sims, scoring, region_wins, max_depth, threads)
+        });
+    }
+}
+
+/// Plays `games` games from `state` between two AIs that run `sims_a` and `sims_b` simulations (see `do_search_sims`)
+/// before each of their moves, taking turns to move first, and returns the number of games that the first AI won, lost
+/// and drew. Each AI keeps playing for the same player all game. Every search runs at least two simulations, so that
+/// the root is always expanded. This is for measuring changes to the search natively, so it isn't built for wasm.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn self_play<G: Game>(state: &G, sims_a: u32, sims_b: u32, games: u32, seed: u64) -> (u32, u32, u32) {
+    let (mut wins, mut losses, mut draws) = (0, 0, 0);
+    for game in 0..games {
+        let game_seed = seed.wrapping_add(2 * game as u64);
+        let mut a = ActionTree::with_seed(state.clone(), game_seed);
+        let mut b = ActionTree::with_seed(state.clone(), game_seed.wrapping_add(1));
+
+        // The first AI takes the first player in even games, and whoever moves after the first move in odd ones
+        let mut a_player = None;
+        while !a.is_game_over() {
+            let player = a.current_player();
+            let a_moves = match &a_player {
+                Some(a_player) => player == *a_player,
+                None if game % 2 == 0 || player != state.current_player() => {
+                    a_player = Some(player);
+                    true
+                }
+                None => false
+            };
+
+            let (mover, sims) = if a_moves { (&mut a, sims_a) } else { (&mut b, sims_b) };
+            let action = self_play_action(mover, sims);
+            a.do_action(&action).unwrap();
+            b.do_action(&action).unwrap();
+        }
+
+        match a.root_state().winner() {
+            Some(winner) if Some(&winner) == a_player.as_ref() => wins += 1,
+            Some(_) => losses += 1,
+            None => draws += 1
+        }
+    }
+    (wins, losses, draws)
+}
+
+// Searches for a self-play move with at least two simulations, so that the root is expanded, and picks the best action.
+// If there isn't one, a random move is drawn from the tree's own seeded RNG, so that games stay reproducible.
+#[cfg(not(target_arch = "wasm32"))]
+fn self_play_action<G: Game>(tree: &mut ActionTree<G>, sims: u32) -> G::Action {
+    tree.do_search_sims(sims.max(2));
+    match tree.get_best_action() {
+        Some((action, _)) => action.clone(),
+        None => tree.nodes.get(tree.root).unwrap().state.available_actions().choose(&mut tree.rng).unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::TicTacToe;
+
+    // A three player game where each player in turn adds 0 or 1 to a total. Once everyone has moved, the player whose
+    // index is the total wins.
+    #[derive(Clone)]
+    struct Rotation {
+        moves: u8,
+        total: u8
+    }
+
+    const TEST_PLAYERS: [u8; 3] = [0, 1, 2];
+
+    impl Game for Rotation {
+        type Action = u8;
+        type Player = u8;
+
+        fn available_actions(&self) -> &[u8] {
+            if self.moves < 3 { &[0, 1] } else { &[] }
+        }
+
+        fn do_action_mut(&mut self, action: &u8) {
+            self.moves += 1;
+            self.total += action;
+        }
+
+        fn get_players(&self) -> &[u8] {
+            &TEST_PLAYERS
+        }
+
+        fn current_player(&self) -> u8 {
+            self.moves % 3
+        }
+
+        fn winner(&self) -> Option<u8> {
+            if self.moves == 3 { Some(self.total) } else { None }
+        }
+    }
+
+    // A two player game of at most two moves: the first player picks one of `first`, and the second player then picks
+    // one of `replies` to it, if there are any. Once no moves are left, `winner` gets the result from the first move
+    // and the reply, if there was one.
+    #[derive(Clone)]
+    struct TwoPly {
+        first: &'static [u8],
+        replies: fn(u8) -> &'static [u8],
+        winner: fn(u8, Option<u8>) -> Option<u8>,
+        first_move: Option<u8>,
+        reply: Option<u8>
+    }
+
+    impl TwoPly {
+        fn new(
+            first: &'static [u8], replies: fn(u8) -> &'static [u8], winner: fn(u8, Option<u8>) -> Option<u8>
+        ) -> Self {
+            TwoPly { first, replies, winner, first_move: None, reply: None }
+        }
+
+        // The first player can either take a draw or gamble. If they gamble, the second player chooses from three
+        // outcomes, only one of which lets the first player win.
+        fn gamble() -> Self {
+            TwoPly::new(
+                &[0, 1],
+                |first| if first == 1 { &[0, 1, 2] } else { &[] },
+                |first, reply| match (first, reply) {
+                    (1, Some(0)) => Some(0),
+                    (1, _) => Some(1),
+                    _ => None
+                }
+            )
+        }
+
+        // The first player can either take a draw or set a trap. If they set the trap, the second player chooses from
+        // ten replies, and wins with the first one but loses with any other.
+        fn trap() -> Self {
+            TwoPly::new(
+                &[0, 1],
+                |first| if first == 1 { &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9] } else { &[] },
+                |first, reply| match (first, reply) {
+                    (1, Some(0)) => Some(1),
+                    (1, _) => Some(0),
+                    _ => None
+                }
+            )
+        }
+
+        // The first player chooses one of five actions, all but the first of which lose immediately. After the first
+        // action, the second player chooses whether the first player wins.
+        fn volatile() -> Self {
+            TwoPly::new(
+                &[0, 1, 2, 3, 4],
+                |first| if first == 0 { &[0, 1] } else { &[] },
+                |first, reply| if (first, reply) == (0, Some(0)) { Some(0) } else { Some(1) }
+            )
+        }
+    }
+
+    impl Game for TwoPly {
+        type Action = u8;
+        type Player = u8;
+
+        fn available_actions(&self) -> &[u8] {
+            match (self.first_move, self.reply) {
+                (None, _) => self.first,
+                (Some(first), None) => (self.replies)(first),
+                _ => &[]
+            }
+        }
+
+        fn do_action_mut(&mut self, action: &u8) {
+            if self.first_move.is_none() {
+                self.first_move = Some(*action);
+            } else {
+                self.reply = Some(*action);
+            }
+        }
+
+        fn get_players(&self) -> &[u8] {
+            &TEST_PLAYERS[..2]
+        }
+
+        fn current_player(&self) -> u8 {
+            (self.first_move.is_some() as u8 + self.reply.is_some() as u8) % 2
+        }
+
+        fn winner(&self) -> Option<u8> {
+            match self.first_move {
+                Some(first) if self.available_actions().is_empty() => (self.winner)(first, self.reply),
+                _ => None
+            }
+        }
+    }
+
+    #[test]
+    fn move_filter_restricts_best_action() {
+        let allowed = [0, 40, 80];
+        let mut tree = ActionTree::new(TicTacToe::new());
+        tree.set_move_filter(&allowed);
+        for _ in 0..200 {
+            tree.do_search_step(10);
+            if let Some((action, _)) = tree.get_best_action() {
+                assert!(allowed.contains(action));
+            }
+        }
+
+        tree.clear_move_filter();
+        let root = tree.nodes.get(tree.root).unwrap();
+        assert_eq!(root.children.len(), 81);
+    }
+
+    #[test]
+    fn smoothed_eval_moves_from_prior_to_winrate() {
+        let mut tree = ActionTree::new(TicTacToe::from_game_record("36 4 37 13").unwrap());
+        assert_eq!(tree.smoothed_eval(100.0), 0.5);
+
+        for _ in 0..2000 {
+            tree.do_search_step(10);
+        }
+        let raw = tree.root_win_probability().unwrap();
+        assert!((tree.smoothed_eval(100.0) - raw).abs() < 0.01);
+        assert!((tree.smoothed_eval(100.0) - 0.5).abs() < (raw - 0.5).abs());
+    }
+
+    #[test]
+    fn stats_csv_has_a_row_per_root_child() {
+        let mut tree = ActionTree::new(TicTacToe::from_game_record("36 4 37 13").unwrap());
+        for _ in 0..50 {
+            tree.do_search_step(10);
+        }
+
+        let csv = tree.stats_csv();
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some("action,visits,wins,winrate,score"));
+        let rows = lines.collect::<Vec<&str>>();
+        assert_eq!(rows.len(), tree.nodes.get(tree.root).unwrap().children.len());
+        assert!(rows.iter().all(|row| row.split(',').count() == 5));
+    }
+
+    #[test]
+    fn get_best_action_handles_hopeless_positions() {
+        let mut tree = ActionTree::new(TicTacToe::new());
+        let root = tree.root;
+        tree.expand(root);
+        let children = tree.nodes.get(root).unwrap().children.values().copied().collect::<Vec<usize>>();
+        for child_id in children {
+            let child = tree.nodes.get_mut(child_id).unwrap();
+            child.visits = 1;
+            child.total_points = 10;
+            child.earned_points = 0;
+        }
+
+        assert!(tree.get_best_action().is_some());
+    }
+
+    #[test]
+    fn search_for_stops_near_budget() {
+        let mut tree = ActionTree::new(TicTacToe::new());
+        let budget = Duration::from_millis(50);
+        let start = std::time::Instant::now();
+        let steps = tree.search_for(budget, 10);
+        let elapsed = start.elapsed();
+
+        assert!(steps > 0);
+        assert!(elapsed >= budget);
+        // Stopping is only checked between steps, so allow plenty of time for slow machines
+        assert!(elapsed < budget + Duration::from_secs(2), "search took {:?}", elapsed);
+    }
+
+    #[test]
+    fn exploration_spreads_visits() {
+        // Returns the share of the root's visits that went to its most visited child
+        fn top_share(exploration: f64) -> f64 {
+            let mut tree = ActionTree::with_exploration(TicTacToe::new(), exploration);
+            for _ in 0..1000 {
+                tree.do_search_step(10);
+            }
+            let (_, child_id) = tree.most_visited_action().unwrap();
+            tree.nodes.get(child_id).unwrap().visits as f64 / tree.nodes.get(tree.root).unwrap().visits as f64
+        }
+
+        assert!(top_share(10.0) < top_share(0.1));
+    }
+
+    #[test]
+    fn same_seed_gives_same_search() {
+        fn best_action(seed: u64) -> Option<u8> {
+            let mut tree = ActionTree::with_seed(TicTacToe::new(), seed);
+            for _ in 0..300 {
+                tree.do_search_step(10);
+            }
+            tree.do_action(&40).unwrap();
+            for _ in 0..300 {
+                tree.do_search_step(10);
+            }
+            tree.get_best_action().map(|(action, _)| *action)
+        }
+
+        for seed in 0..5 {
+            assert_eq!(best_action(seed), best_action(seed));
+        }
+    }
+
+    #[test]
+    fn simulate_counts_draws() {
+        use crate::k_in_a_row::KInARow;
+
+        // The only move left fills the board without a line for either player
+        let mut state = KInARow::regular();
+        for &action in [0, 1, 2, 4, 3, 5, 7, 6].iter() {
+            state.do_action_mut(&action);
+        }
+        let mut rng = SmallRng::seed_from_u64(0);
+        let result = simulate(&mut rng, &state, 100, Scoring::default(), &mut Vec::new(), None);
+        assert_eq!(result.draws, 100);
+        assert_eq!(result.total_points, 100 * Scoring::default().win);
+
+        state.do_action_mut(&8);
+        assert!(state.game_over());
+        let result = simulate(&mut rng, &state, 100, Scoring::default(), &mut Vec::new(), None);
+        assert_eq!(result.draws, 100);
+
+        // A game that's been won is never drawn
+        let mut state = KInARow::regular();
+        for &action in [0, 3, 1, 4, 2].iter() {
+            state.do_action_mut(&action);
+        }
+        assert_eq!(simulate(&mut rng, &state, 100, Scoring::default(), &mut Vec::new(), None).draws, 0);
+
+        let mut tree = ActionTree::with_seed(KInARow::regular(), 0);
+        for _ in 0..100 {
+            tree.do_search_step(10);
+        }
+        let draws = tree.get_node_draws(tree.root);
+        assert!(draws > 0 && draws < 1000);
+    }
+
+    #[test]
+    fn node_limit_caps_tree_size() {
+        let mut tree = ActionTree::with_node_limit(TicTacToe::new(), 200);
+        for _ in 0..2000 {
+            tree.do_search_step(10);
+            assert!(tree.node_count() <= 200);
+        }
+
+        let (action, _) = tree.get_best_action().unwrap();
+        assert!(tree.root_state().available_actions().contains(action));
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn more_simulations_win_self_play() {
+        let (wins, losses, draws) = self_play(&TicTacToe::new(), 1000, 100, 10, 1);
+        assert_eq!(wins + losses + draws, 10);
+        assert!(wins >= losses + 5, "won {}, lost {}, drew {}", wins, losses, draws);
+
+        // The second AI's seed wraps around instead of overflowing
+        let (wins, losses, draws) = self_play(&TicTacToe::new(), 1, 1, 1, u64::MAX);
+        assert_eq!(wins + losses + draws, 1);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn self_play_searches_with_a_single_simulation() {
+        let first_moves = (0..10).map(|seed| {
+            let mut tree = ActionTree::with_seed(TicTacToe::new(), seed);
+            let action = self_play_action(&mut tree, 1);
+            assert!(tree.node_count() > 1);
+            action
+        }).collect::<HashSet<u8>>();
+        assert!(first_moves.len() > 1, "every seed played {:?}", first_moves);
+    }
+
+    #[test]
+    fn tiny_node_limit_still_expands_the_root() {
+        let mut tree = ActionTree::with_node_limit(TicTacToe::new(), 5);
+        tree.do_search_iterations(100, 10);
+        assert_eq!(tree.node_count(), 82);
+        let (action, _) = tree.get_best_action().unwrap();
+        assert!(tree.root_state().available_actions().contains(action));
+    }
+
+    #[test]
+    fn backpropagate_credits_each_mover() {
+        let mut tree = ActionTree::new(Rotation { moves: 0, total: 0 });
+        let mut path = vec![tree.root];
+        for _ in 0..3 {
+            let child = tree.expand(*path.last().unwrap());
+            path.push(child);
+        }
+
+        let earned_points = [(0, 1), (1, 2), (2, 4)].iter().copied().collect::<HashMap<u8, u32>>();
+        let result = SimResult { total_points: 7, earned_points, squared_points: HashMap::new(), draws: 0 };
+        tree.backpropagate(path[3], 1, result);
+
+        // The nodes one, two and three moves deep were chosen by players 0, 1 and 2 respectively
+        let earned = path[1..].iter().map(|&id| tree.get_node_earned_points(id)).collect::<Vec<u32>>();
+        assert_eq!(earned, vec![1, 2, 4]);
+    }
+
+    #[test]
+    fn three_player_search_finds_wins() {
+        // Player 2 moves last, so it can always choose the total that makes it win if the total is already 1
+        let mut tree = ActionTree::new(Rotation { moves: 2, total: 1 });
+        for _ in 0..100 {
+            tree.do_search_step(1);
+        }
+        assert_eq!(tree.get_best_action().map(|(action, _)| *action), Some(1));
+    }
+
+    #[test]
+    fn ucb1_tuned_explores_bad_moves_less() {
+        // The first player only has one move that doesn't lose right away, which wins about as often as it loses when played randomly
+        // Returns the share of the root's visits that went to the losing moves
+        fn bad_share(policy: SelectionPolicy) -> f64 {
+            // Without room to expand the root's children, every move is only ever evaluated by random playouts
+            let mut tree = ActionTree::with_node_limit(TwoPly::volatile(), 6);
+            tree.set_policy(policy);
+            for _ in 0..2000 {
+                tree.do_search_step(1);
+            }
+            let root = tree.nodes.get(tree.root).unwrap();
+            let bad_visits = root.children.iter()
+                .filter(|(&action, _)| action != 0)
+                .map(|(_, &id)| tree.get_node_visits(id))
+                .sum::<u32>();
+            bad_visits as f64 / root.visits as f64
+        }
+
+        assert!(bad_share(SelectionPolicy::Ucb1Tuned) < bad_share(SelectionPolicy::Uct));
+    }
+
+    #[test]
+    fn seeded_visits_bias_early_selection() {
+        // Returns how many of the first simulations went through the move in the corner of the corner sub-board
+        fn corner_visits(seed: u32) -> u32 {
+            let mut tree = ActionTree::with_seed(TicTacToe::new(), 6);
+            if seed > 0 {
+                tree.seed_children(vec![(0, seed)].into_iter().collect()).unwrap();
+            }
+            let corner = tree.nodes.get(tree.root).unwrap().children.get(&0).copied();
+            if seed > 0 {
+                let (exploitation, exploration) = tree.score_components(&0).unwrap();
+                assert!((exploitation + exploration - tree.nodes.get(corner.unwrap()).unwrap().score).abs() < 1e-9);
+            }
+            tree.do_search_iterations(2000, 1);
+            let corner = corner.unwrap_or_else(|| tree.nodes.get(tree.root).unwrap().children[&0]);
+            tree.get_node_visits(corner) - seed
+        }
+
+        let unseeded = corner_visits(0);
+        assert!(corner_visits(10) > 2 * unseeded, "seeded moves should be searched more than unseeded ones");
+    }
+
+    #[test]
+    fn seeding_too_many_visits_fails() {
+        let searched_tree = || {
+            let mut tree = ActionTree::with_seed(TicTacToe::new(), 0);
+            tree.do_search_step(10);
+            tree
+        };
+        let tree = searched_tree();
+        let points = tree.nodes.get(tree.root).unwrap().total_points;
+        let max_visits = (u32::MAX - points) / Scoring::default().win;
+
+        // Visits for actions that can't be played don't count
+        let priors = vec![(0, max_visits), (200, u32::MAX)].into_iter().collect();
+        assert_eq!(searched_tree().seed_children(priors), Ok(()));
+
+        for priors in [vec![(0, max_visits + 1)], vec![(0, max_visits), (1, 1)], vec![(0, u32::MAX), (1, u32::MAX)]] {
+            let mut tree = searched_tree();
+            assert_eq!(tree.seed_children(priors.into_iter().collect()), Err(TooManyVisits));
+            assert_eq!(tree.get_node_visits(tree.root), 10);
+            assert!(tree.ranked_actions().iter().all(|&(_, child_id)| tree.get_node_visits(child_id) == 0));
+        }
+    }
+
+    #[test]
+    fn score_components_add_up_to_the_score() {
+        for &policy in &[SelectionPolicy::Uct, SelectionPolicy::Ucb1Tuned, SelectionPolicy::Puct] {
+            let mut tree = ActionTree::new(TicTacToe::new());
+            tree.set_policy(policy);
+            let visits = |tree: &ActionTree<TicTacToe>| {
+                let root = tree.nodes.get(tree.root).unwrap();
+                root.children.iter().map(|(&action, &id)| (action, tree.get_node_visits(id))).collect::<HashMap<_, _>>()
+            };
+
+            let mut checked = 0;
+            for _ in 0..300 {
+                let before = visits(&tree);
+                tree.do_search_step(1);
+
+                // Only the child the simulation went through has had its score worked out from the root's current
+                // statistics. The first simulation is of the root itself.
+                let after = visits(&tree);
+                let (action, _) = match after.iter().find(|(action, &v)| v > before.get(action).copied().unwrap_or(0)) {
+                    Some(changed) => changed,
+                    None => continue
+                };
+                checked += 1;
+                let child_id = tree.nodes.get(tree.root).unwrap().children[action];
+                let (exploitation, exploration) = tree.score_components(action).unwrap();
+                assert!(
+                    (exploitation + exploration - tree.nodes.get(child_id).unwrap().score).abs() < 1e-9,
+                    "{:?}: components don't add up for {}", policy, action
+                );
+                assert!((0.0..=1.0).contains(&exploitation));
+                assert!(exploration >= 0.0);
+            }
+            assert!(checked > 250);
+            assert_eq!(tree.score_components(&200), None);
+        }
+    }
+
+    #[test]
+    fn scoring_changes_attitude_to_draws() {
+        fn preferred_action(scoring: Scoring) -> Option<u8> {
+            // Without room to expand the root's children, the gamble is only evaluated by random playouts and pays off
+            // a third of the time
+            let mut tree = ActionTree::with_node_limit(TwoPly::gamble(), 3);
+            tree.set_scoring(scoring);
+            for _ in 0..1000 {
+                tree.do_search_step(10);
+            }
+            tree.get_best_action().map(|(action, _)| *action)
+        }
+
+        assert_eq!(preferred_action(Scoring::default()), Some(1));
+        assert_eq!(preferred_action(Scoring { win: 10, draw: 5 }), Some(0));
+    }
+
+    #[test]
+    fn max_backup_sees_through_the_trap_sooner() {
+        fn preferred_action(backup: BackupPolicy, sims: u32) -> Option<u8> {
+            let mut tree = ActionTree::with_seed(TwoPly::trap(), 2);
+            tree.set_backup_policy(backup);
+            for _ in 0..sims {
+                tree.do_search_step(1);
+            }
+            tree.get_best_action().map(|(action, _)| *action)
+        }
+
+        // Taking the draw is the only move that doesn't lose to the best reply, but most replies to the trap lose, so
+        // averaging keeps preferring the trap until the search has focused on the one reply that beats it
+        assert_eq!(preferred_action(BackupPolicy::Max, 100), Some(0));
+        assert_eq!(preferred_action(BackupPolicy::Average, 100), Some(1));
+        assert_eq!(preferred_action(BackupPolicy::Average, 1000), Some(0));
+    }
+
+    #[test]
+    fn half_win_draws_score_as_even() {
+        fn draw_winrate(scoring: Scoring) -> f64 {
+            // Taking the draw ends the game straight away, so every simulation of it is a draw
+            let mut tree = ActionTree::with_seed(TwoPly::gamble(), 4);
+            tree.set_scoring(scoring);
+            for _ in 0..200 {
+                tree.do_search_step(10);
+            }
+            let child_id = tree.nodes.get(tree.root).unwrap().children[&0];
+            tree.node_winrate(child_id)
+        }
+
+        assert!((draw_winrate(Scoring::default()) - 0.1).abs() < 1e-9);
+        assert!((draw_winrate(Scoring::draws_as_half_wins()) - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn walk_visits_every_node() {
+        let mut tree = ActionTree::with_seed(TicTacToe::new(), 3);
+        for _ in 0..200 {
+            tree.do_search_step(1);
+        }
+
+        let views = tree.walk(None).collect::<Vec<_>>();
+        assert_eq!(views.len(), tree.node_count());
+        assert_eq!(views[0].id, tree.root);
+        assert_eq!((views[0].parent, views[0].action, views[0].depth), (None, None, 0));
+
+        // Each node is one move below its parent, which reaches it with the node's action
+        let depths = views.iter().map(|view| (view.id, view.depth)).collect::<HashMap<usize, usize>>();
+        for view in &views[1..] {
+            let parent = view.parent.unwrap();
+            assert_eq!(depths[&parent] + 1, view.depth);
+            assert_eq!(tree.nodes.get(parent).unwrap().children[view.action.unwrap()], view.id);
+            assert_eq!(view.visits, tree.get_node_visits(view.id));
+        }
+
+        let shallow = tree.walk(Some(1)).collect::<Vec<_>>();
+        assert_eq!(shallow.len(), 1 + tree.nodes.get(tree.root).unwrap().children.len());
+        assert!(shallow.iter().all(|view| view.depth <= 1));
+    }
+
+    #[test]
+    fn stats_describe_the_tree() {
+        let mut tree = ActionTree::new(TicTacToe::new());
+        assert_eq!(tree.stats(), TreeStats { node_count: 1, root_visits: 0, max_depth: 0, root_children: 0 });
+
+        for _ in 0..500 {
+            tree.do_search_step(10);
+        }
+        let stats = tree.stats();
+        let root = tree.nodes.get(tree.root).unwrap();
+        let child_visits = root.children.values().map(|&id| tree.get_node_visits(id)).sum::<u32>();
+        // The first step simulates the root itself before it has any children
+        assert_eq!(stats.root_visits, child_visits + 10);
+        assert_eq!(stats.node_count, tree.nodes.len());
+        assert_eq!(stats.root_children, 81);
+        assert!(stats.max_depth >= 1);
+
+        let json = stats.to_string();
+        assert!(json.starts_with("{\"node_count\":") && json.ends_with('}'));
+    }
+
+    #[test]
+    fn final_move_selection_handles_lucky_actions() {
+        let mut tree = ActionTree::new(TicTacToe::new());
+        let root = tree.root;
+        tree.expand(root);
+        let children = tree.nodes.get(root).unwrap().children.clone();
+
+        // One action has been simulated a lot and wins often, while another won the only time it was simulated
+        let (steady, lucky) = (children[&40], children[&0]);
+        for &(child_id, visits, earned_points) in [(steady, 1000, 6500), (lucky, 1, 10)].iter() {
+            let child = tree.nodes.get_mut(child_id).unwrap();
+            child.visits = visits;
+            child.total_points = 10 * visits;
+            child.earned_points = earned_points;
+        }
+
+        let best = |tree: &ActionTree<TicTacToe>| tree.get_best_action().map(|(action, _)| *action);
+        assert_eq!(best(&tree), Some(0));
+        tree.set_final_move_selection(FinalMoveSelection::MostVisited);
+        assert_eq!(best(&tree), Some(40));
+        tree.set_final_move_selection(FinalMoveSelection::MaxLowerBound);
+        assert_eq!(best(&tree), Some(40));
+    }
+
+    #[test]
+    fn sample_gamma_has_the_right_mean() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        for &alpha in [0.3, 1.0, 2.5].iter() {
+            let mean = (0..10000).map(|_| sample_gamma(&mut rng, alpha)).sum::<f64>() / 10000.0;
+            assert!((mean - alpha).abs() < alpha * 0.1, "mean of Gamma({}) samples was {}", alpha, mean);
+        }
+    }
+
+    #[test]
+    fn root_noise_varies_the_first_move() {
+        // Returns the first action the search tries after the root, with or without noise
+        fn first_move(seed: u64, noise: bool) -> u8 {
+            let mut tree = ActionTree::with_seed(TicTacToe::new(), seed);
+            tree.set_policy(SelectionPolicy::Puct);
+            tree.do_search_step(10);
+            if noise {
+                tree.add_root_noise(0.25, 0.3);
+            }
+            tree.do_search_step(10);
+            *tree.most_visited_action().unwrap().0
+        }
+
+        let plain = (0..20).map(|seed| first_move(seed, false)).collect::<HashSet<u8>>();
+        let noisy = (0..20).map(|seed| first_move(seed, true)).collect::<HashSet<u8>>();
+        assert_eq!(plain.len(), 1);
+        assert!(noisy.len() > 1);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialized_tree_round_trips() {
+        let mut tree = ActionTree::new(TicTacToe::new());
+        for _ in 0..300 {
+            tree.do_search_step(10);
+        }
+
+        let json = serde_json::to_string(&tree).unwrap();
+        let loaded: ActionTree<TicTacToe> = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.get_best_action(), tree.get_best_action());
+        assert_eq!(loaded.stats(), tree.stats());
+        assert_eq!(loaded.root_state(), tree.root_state());
+    }
+
+    #[test]
+    fn do_action_removes_exactly_the_unreachable_nodes() {
+        let mut rng = SmallRng::seed_from_u64(1);
+        let mut tree = ActionTree::new(TicTacToe::new());
+        while !tree.is_game_over() {
+            for _ in 0..200 {
+                tree.do_search_step(10);
+            }
+            let action = *tree.root_state().available_actions().choose(&mut rng).unwrap();
+            tree.do_action(&action).unwrap();
+
+            // A full scan for unreachable nodes shouldn't find anything left to remove
+            let surviving = tree.nodes.iter().map(|(id, _)| id).collect::<Vec<usize>>();
+            tree.collect_garbage();
+            let reachable = tree.nodes.iter().map(|(id, _)| id).collect::<Vec<usize>>();
+            assert_eq!(surviving, reachable);
+        }
+    }
+
+    #[test]
+    fn expanding_twice_keeps_child_statistics() {
+        let mut tree = ActionTree::with_seed(TicTacToe::new(), 4);
+        for _ in 0..500 {
+            tree.do_search_step(10);
+        }
+        let children = |tree: &ActionTree<TicTacToe>| {
+            tree.nodes.get(tree.root).unwrap().children.values()
+                .map(|&id| (id, tree.nodes.get(id).unwrap().visits))
+                .collect::<HashMap<usize, u32>>()
+        };
+        let before = children(&tree);
+        assert!(before.values().any(|&visits| visits > 0));
+
+        assert_eq!(tree.expand(tree.root), tree.root);
+        assert_eq!(children(&tree), before);
+    }
+
+    #[test]
+    fn terminal_nodes_are_solved_without_playouts() {
+        use crate::k_in_a_row::KInARow;
+
+        // X has two in a row along the top and can win by playing in the corner
+        let mut state = KInARow::regular();
+        for action in [0, 3, 1, 4] {
+            state.do_action_mut(&action);
+        }
+        let mut tree = ActionTree::with_seed(state, 5);
+        for _ in 0..30 {
+            tree.do_search_step(10);
+        }
+
+        let root = tree.nodes.get(tree.root).unwrap();
+        let winning_child = *root.children.get(&2).unwrap();
+        assert!(tree.nodes.get(winning_child).unwrap().terminal);
+        assert!(tree.get_node_visits(winning_child) > 0);
+        assert_eq!(tree.node_winrate(winning_child), 1.0);
+        assert!(tree.nodes.get(winning_child).unwrap().children.is_empty());
+        assert_eq!(tree.get_best_action().map(|(action, _)| *action), Some(2));
+    }
+
+    fn solve(moves: &[u8]) -> (ActionTree<crate::k_in_a_row::KInARow>, Option<ProvenOutcome>) {
+        let mut state = crate::k_in_a_row::KInARow::regular();
+        for action in moves {
+            state.do_action_mut(action);
+        }
+        let mut tree = ActionTree::with_seed(state, 6);
+        tree.set_solver(true);
+        for _ in 0..100_000 {
+            if tree.proven_result().is_some() {
+                break;
+            }
+            tree.do_search_step(1);
+        }
+        let result = tree.proven_result();
+        (tree, result)
+    }
+
+    #[test]
+    fn solver_proves_immediate_win() {
+        // X can complete the top row, even though O is also threatening to win
+        let (tree, result) = solve(&[0, 3, 1, 4]);
+        assert_eq!(result, Some(ProvenOutcome::Win));
+        assert_eq!(tree.get_best_action().map(|(action, _)| *action), Some(2));
+    }
+
+    #[test]
+    fn solver_proves_loss_against_double_threat() {
+        // O threatens both 2 and 6, and X can't win first
+        let (_, result) = solve(&[4, 0, 5, 1, 7, 3]);
+        assert_eq!(result, Some(ProvenOutcome::Loss));
+    }
+
+    #[test]
+    fn solver_proves_draw_after_opening() {
+        // Tic-tac-toe is a draw with perfect play, including after X takes the center and O takes a corner
+        let (_, result) = solve(&[4, 0]);
+        assert_eq!(result, Some(ProvenOutcome::Draw));
+    }
+
+    #[test]
+    fn solver_prefers_proven_win_over_statistics() {
+        let (mut tree, _) = solve(&[0, 3, 1, 4]);
+        tree.set_final_move_selection(FinalMoveSelection::MostVisited);
+        assert_eq!(tree.get_best_action().map(|(action, _)| *action), Some(2));
+    }
+
+    #[test]
+    fn concedes_lost_and_drawn_positions() {
+        fn searched(moves: &[u8], scoring: Scoring, sims: u32) -> ActionTree<crate::k_in_a_row::KInARow> {
+            let mut state = crate::k_in_a_row::KInARow::regular();
+            for action in moves {
+                state.do_action_mut(action);
+            }
+            let mut tree = ActionTree::with_seed(state, 8);
+            tree.set_scoring(scoring);
+            for _ in 0..sims / 10 {
+                tree.do_search_step(10);
+            }
+            tree
+        }
+
+        // O threatens both 2 and 6, but X shouldn't give up before searching enough
+        assert!(!searched(&[4, 0, 5, 1, 7, 3], Scoring::default(), 100).should_resign(0.2));
+        let lost = searched(&[4, 0, 5, 1, 7, 3], Scoring::default(), 2000);
+        assert!(lost.should_resign(0.2));
+        assert!(!lost.should_claim_draw(0.05));
+
+        // X can still win after the opening move, so it neither resigns nor settles for a draw
+        let opening = searched(&[4], Scoring::draws_as_half_wins(), 5000);
+        assert!(!opening.should_resign(0.2));
+        assert!(!opening.should_claim_draw(0.05));
+
+        // A proven draw can be claimed straight away
+        let (drawn, _) = solve(&[4, 0]);
+        assert!(drawn.should_claim_draw(0.0));
+        assert!(!drawn.should_resign(0.2));
+    }
+
+    #[test]
+    fn pondering_keeps_statistics_for_the_reply() {
+        let mut state = TicTacToe::new();
+        state.do_action_mut(&40);
+
+        let mut pondered = ActionTree::with_seed(state.clone(), 7);
+        for _ in 0..500 {
+            pondered.ponder_step(10);
+        }
+        // Every reply was searched, not just the ones that look best for the opponent
+        let root = pondered.nodes.get(pondered.root).unwrap();
+        assert!(root.children.values().all(|id| pondered.get_node_visits(*id) > 0));
+
+        let mut cold = ActionTree::with_seed(state, 7);
+        pondered.do_action(&36).unwrap();
+        cold.do_action(&36).unwrap();
+        assert!(pondered.get_node_visits(pondered.root) > 0);
+        assert_eq!(cold.get_node_visits(cold.root), 0);
+    }
+
+    #[test]
+    fn book_moves_are_played_without_searching() {
+        let mut tree = ActionTree::with_opening_book(TicTacToe::new(), TicTacToe::opening_book());
+        assert_eq!(tree.consult_book(), Some(40));
+        assert_eq!(tree.get_node_visits(tree.root), 0);
+
+        // Positions that aren't in the book have to be searched
+        tree.do_action(&40).unwrap();
+        assert_eq!(tree.consult_book(), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn opening_book_loads_from_json() {
+        let code = TicTacToe::new().to_code();
+        let book = OpeningBook::<u8>::from_json(&format!("{{\"{}\": 36}}", code)).unwrap();
+        assert_eq!(book.get(&code), Some(&36));
+        assert!(OpeningBook::<u8>::from_json("[36]").is_err());
+    }
+
+    #[test]
+    fn sample_action_temperature_controls_spread() {
+        let mut tree = ActionTree::with_seed(TicTacToe::new(), 8);
+        for _ in 0..2000 {
+            tree.do_search_step(10);
+        }
+        let most_visited = *tree.most_visited_action().unwrap().0;
+
+        let cold = (0..200).map(|_| tree.sample_action(0.01).unwrap()).collect::<Vec<u8>>();
+        assert!(cold.iter().filter(|&&action| action == most_visited).count() >= 198);
+        assert_eq!(tree.sample_action(0.0), Some(most_visited));
+
+        let hot = (0..200).map(|_| tree.sample_action(10.0).unwrap()).collect::<HashSet<u8>>();
+        assert!(hot.len() > 20, "only sampled {} actions", hot.len());
+    }
+
+    #[test]
+    fn efficiency_metrics_tell_narrow_searches_from_wide_ones() {
+        let fresh = ActionTree::new(TicTacToe::new());
+        assert_eq!(fresh.effective_branching(), 0.0);
+        assert_eq!(fresh.simulations_per_node(), 0.0);
+
+        // The same number of simulations, in a few large steps or many small ones
+        let mut few_steps = ActionTree::with_seed(TicTacToe::new(), 0);
+        few_steps.do_search_iterations(100, 100);
+        let mut many_steps = ActionTree::with_seed(TicTacToe::new(), 0);
+        many_steps.do_search_iterations(10_000, 1);
+        assert!(few_steps.simulations_per_node() > many_steps.simulations_per_node());
+
+        // The same steps, exploring less or more
+        let mut deep = ActionTree::with_seed(TicTacToe::new(), 0);
+        deep.set_exploration(0.2);
+        deep.do_search_iterations(2000, 5);
+        let mut wide = ActionTree::with_seed(TicTacToe::new(), 0);
+        wide.set_exploration(5.0);
+        wide.do_search_iterations(2000, 5);
+        assert!(
+            deep.effective_branching() < wide.effective_branching(),
+            "deep search branched {}, wide search {}", deep.effective_branching(), wide.effective_branching()
+        );
+
+        // A tree with every node expanded branches by its actual branching factor
+        let stats = wide.stats();
+        let b = wide.effective_branching();
+        let levels = (0..=stats.max_depth as i32).map(|depth| b.powi(depth)).sum::<f64>();
+        assert!((levels / stats.node_count as f64 - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn each_iteration_simulates_a_new_node() {
+        let visited = |tree: &ActionTree<TicTacToe>| tree.walk(None).filter(|node| node.visits > 0).count();
+
+        let mut tree = ActionTree::with_seed(TicTacToe::new(), 0);
+        tree.do_search_iterations(50, 4);
+        assert_eq!(visited(&tree), 50);
+        assert_eq!(tree.get_node_visits(tree.root), 200);
+
+        tree.do_search_iterations(10, 20);
+        assert_eq!(visited(&tree), 60);
+        assert_eq!(tree.get_node_visits(tree.root), 400);
+    }
+
+    #[test]
+    fn search_sims_are_split_into_steps() {
+        let visited = |tree: &ActionTree<TicTacToe>| tree.walk(None).filter(|node| node.visits > 0).count();
+
+        let mut tree = ActionTree::with_seed(TicTacToe::new(), 0);
+        tree.do_search_sims(50);
+        assert_eq!(visited(&tree), 50);
+        assert_eq!(tree.get_node_visits(tree.root), 50);
+
+        // 250 simulations make 125 steps of 2
+        tree.do_search_sims(250);
+        assert_eq!(visited(&tree), 175);
+        assert_eq!(tree.get_node_visits(tree.root), 300);
+
+        // 20,050 simulations make 200 steps of 100, plus one with the 50 left over
+        tree.do_search_sims(20_050);
+        assert_eq!(tree.get_node_visits(tree.root), 20_350);
+    }
+
+    #[test]
+    fn illegal_actions_are_rejected() {
+        let mut tree = ActionTree::with_seed(TicTacToe::from_game_record("40 36").unwrap(), 0);
+        for _ in 0..100 {
+            tree.do_search_step(10);
+        }
+        let visits = tree.get_node_visits(tree.root);
+        let nodes = tree.node_count();
+
+        // 40 is already taken, 80 is outside the active sub-board, and 81 isn't a cell
+        for &action in [40, 80, 81].iter() {
+            assert_eq!(tree.do_action(&action), Err(IllegalAction));
+        }
+        assert_eq!(tree.root_state(), &TicTacToe::from_game_record("40 36").unwrap());
+        assert_eq!((tree.get_node_visits(tree.root), tree.node_count()), (visits, nodes));
+
+        // A legal action that was never expanded still works
+        let mut fresh = ActionTree::new(TicTacToe::new());
+        assert_eq!(fresh.do_action(&40), Ok(()));
+        assert_eq!(fresh.do_action(&40), Err(IllegalAction));
+    }
+
+    #[test]
+    fn randomized_first_moves_vary_between_games() {
+        let mut openings = HashSet::new();
+        for seed in 0..20 {
+            let mut tree = ActionTree::with_seed(crate::k_in_a_row::KInARow::regular(), seed);
+            for _ in 0..200 {
+                tree.do_search_step(10);
+            }
+            let mut visits = tree.ranked_actions().iter()
+                .map(|(_, id)| tree.get_node_visits(*id))
+                .collect::<Vec<u32>>();
+            visits.sort_unstable_by_key(|&visits| std::cmp::Reverse(visits));
+
+            let action = tree.randomize_first_move(3).unwrap();
+            let (_, child_id) = tree.ranked_actions().into_iter().find(|(a, _)| **a == action).unwrap();
+            assert!(tree.get_node_visits(child_id) >= visits[2]);
+            openings.insert(action);
+
+            // After the first move, the best move is always played
+            tree.do_action(&action).unwrap();
+            for _ in 0..200 {
+                tree.do_search_step(10);
+            }
+            let best = *tree.get_best_action().unwrap().0;
+            assert_eq!(tree.randomize_first_move(3), Some(best));
+        }
+        assert!(openings.len() >= 2, "only opened with {:?}", openings);
+    }
+
+    #[test]
+    fn search_with_callback_reports_progress() {
+        let mut tree = ActionTree::with_seed(TicTacToe::from_game_record("36 4 37 13").unwrap(), 9);
+        tree.set_final_move_selection(FinalMoveSelection::MostVisited);
+        let mut reports = Vec::new();
+        tree.search_with_callback(5050, 500, |sims, action| reports.push((sims, action.copied())));
+
+        // Every 500 simulations, plus the 50 left over at the end
+        assert_eq!(reports.len(), 11);
+        assert_eq!(reports.first().unwrap().0, 500);
+        assert_eq!(reports.last().unwrap().0, 5050);
+        assert!(reports.iter().all(|(_, action)| action.is_some()));
+
+        // The best action settles down as the search goes on, although close moves can still swap places now and then
+        let best = tree.get_best_action().map(|(action, _)| *action);
+        assert_eq!(reports.last().unwrap().1, best);
+        assert!(reports.iter().filter(|(_, action)| *action == best).count() >= reports.len() * 2 / 3);
+    }
+
+    #[test]
+    fn selection_ties_go_to_the_lowest_node_id() {
+        let mut tree = ActionTree::new(TicTacToe::new());
+        tree.expand(tree.root);
+        let lowest = *tree.nodes.get(tree.root).unwrap().children.values().min().unwrap();
+        assert_eq!(tree.select(tree.root), lowest);
+    }
+
+    #[test]
+    fn truncated_rollouts_decide_sooner() {
+        use crate::game::Player;
+        use std::cell::Cell;
+
+        thread_local! {
+            // The number of moves played and positions evaluated by `Counted` games on this thread
+            static MOVES: Cell<usize> = const { Cell::new(0) };
+            static EVALUATIONS: Cell<usize> = const { Cell::new(0) };
+        }
+
+        // Ultimate tic-tac-toe, counting the moves played and positions evaluated
+        #[derive(Clone)]
+        struct Counted(TicTacToe);
+
+        impl Game for Counted {
+            type Action = u8;
+            type Player = Player;
+
+            fn available_actions(&self) -> &[u8] {
+                self.0.available_actions()
+            }
+
+            fn do_action_mut(&mut self, action: &u8) {
+                MOVES.with(|moves| moves.set(moves.get() + 1));
+                self.0.do_action_mut(action);
+            }
+
+            fn get_players(&self) -> &[Player] {
+                self.0.get_players()
+            }
+
+            fn current_player(&self) -> Player {
+                self.0.current_player()
+            }
+
+            fn winner(&self) -> Option<Player> {
+                self.0.winner()
+            }
+
+            fn evaluate<R: Rng>(&self, rng: &mut R, scoring: Scoring) -> HashMap<Player, u32> {
+                EVALUATIONS.with(|evaluations| evaluations.set(evaluations.get() + 1));
+                self.0.evaluate(rng, scoring)
+            }
+        }
+
+        // Returns the moves played and positions evaluated by a search, and the action it picked
+        fn counted_search(max_depth: Option<u32>) -> (usize, usize, Option<u8>) {
+            MOVES.with(|moves| moves.set(0));
+            EVALUATIONS.with(|evaluations| evaluations.set(0));
+            let mut tree = ActionTree::with_seed(Counted(TicTacToe::new()), 4);
+            tree.set_rollout_depth(max_depth);
+            for _ in 0..200 {
+                tree.do_search_step(10);
+            }
+            let action = tree.get_best_action().map(|(action, _)| *action);
+            (MOVES.with(Cell::get), EVALUATIONS.with(Cell::get), action)
+        }
+
+        let (full_moves, full_evaluations, full_action) = counted_search(None);
+        let (truncated_moves, truncated_evaluations, truncated_action) = counted_search(Some(6));
+        assert!(full_action.is_some() && truncated_action.is_some());
+        assert_eq!(full_evaluations, 0);
+        // Every simulation from the opening is cut off before the game can end
+        assert_eq!(truncated_evaluations, 2000);
+        assert!(truncated_moves < full_moves / 3, "truncated {} moves, full {}", truncated_moves, full_moves);
+    }
+
+    #[test]
+    fn fpu_skips_unpromising_root_moves() {
+        fn unvisited_root_children(fpu_reduction: Option<f64>) -> usize {
+            let mut tree = ActionTree::with_seed(TicTacToe::new(), 1);
+            tree.set_fpu_reduction(fpu_reduction);
+            for _ in 0..300 {
+                tree.do_search_step(1);
+            }
+            let root = tree.nodes.get(tree.root).unwrap();
+            root.children.values().filter(|&&id| tree.get_node_visits(id) == 0).count()
+        }
+
+        assert_eq!(unvisited_root_children(None), 0);
+        assert!(unvisited_root_children(Some(0.2)) > 0);
+    }
+
+    #[test]
+    fn seeded_searches_are_reproducible() {
+        let search = || {
+            let mut tree = ActionTree::with_seed(TicTacToe::new(), 10);
+            for _ in 0..2000 {
+                tree.do_search_step(5);
+            }
+            (format!("{:?}", tree.principal_variation()), tree.stats().to_string())
+        };
+        assert_eq!(search(), search());
+    }
+
+    #[test]
+    fn nan_scores_do_not_break_selection() {
+        // With no points for anything, every winrate is 0 / 0
+        for &policy in [SelectionPolicy::Uct, SelectionPolicy::Ucb1Tuned, SelectionPolicy::Puct].iter() {
+            let mut tree = ActionTree::with_seed(TicTacToe::new(), 11);
+            tree.set_scoring(Scoring { win: 0, draw: 0 });
+            tree.set_policy(policy);
+            for _ in 0..200 {
+                tree.do_search_step(5);
+            }
+            assert!(tree.nodes.iter().all(|(_, node)| !node.score.is_nan()));
+        }
+
+        let mut tree = ActionTree::new(TicTacToe::new());
+        tree.expand(tree.root);
+        for (_, node) in tree.nodes.iter_mut() {
+            node.score = f64::NAN;
+        }
+        let lowest = *tree.nodes.get(tree.root).unwrap().children.values().min().unwrap();
+        assert_eq!(tree.select(tree.root), lowest);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_simulation_matches_sequential_results() {
+        const SIMS: u32 = 4000;
+        let state = TicTacToe::from_game_record("36 4 37 13").unwrap();
+        let winrate = |points: &HashMap<crate::Player, u32>, total: u32| {
+            points[&crate::Player::X] as f64 / total as f64
+        };
+
+        let mut rng = SmallRng::seed_from_u64(12);
+        let mut region_wins = Vec::new();
+        let result = simulate(&mut rng, &state, SIMS, Scoring::default(), &mut region_wins, None);
+        let sequential = winrate(&result.earned_points, result.total_points);
+
+        let mut rng = SmallRng::seed_from_u64(12);
+        let mut parallel_region_wins = Vec::new();
+        let parallel =
+            simulate_parallel(&mut rng, &state, SIMS, Scoring::default(), &mut parallel_region_wins, None, 4);
+        assert_eq!(parallel.total_points, result.total_points);
+        assert!((winrate(&parallel.earned_points, parallel.total_points) - sequential).abs() < 0.05);
+        assert!((parallel.draws as f64 - result.draws as f64).abs() < 0.05 * SIMS as f64);
+
+        // Every simulation is counted once for each region someone won
+        let region_count = |region_wins: &Vec<HashMap<crate::Player, u32>>| {
+            region_wins.iter().flat_map(|wins| wins.values()).sum::<u32>() as f64
+        };
+        assert!((region_count(&parallel_region_wins) / region_count(&region_wins) - 1.0).abs() < 0.05);
+
+        let mut tree = ActionTree::with_seed(state, 12);
+        for _ in 0..50 {
+            tree.do_parallel_search_step(40, 4);
+        }
+        assert_eq!(tree.get_node_visits(tree.root), 50 * 40);
+    }
+
+    // The allocation counter replaces the global allocator, so it can't be used when `wee_alloc` is
+    #[cfg(not(feature = "wee_alloc"))]
+    mod allocations {
+        use super::*;
+        use std::alloc::{ GlobalAlloc, Layout, System };
+        use std::cell::Cell;
+
+        // Counts the allocations made by each thread, so that tests can check how much work allocates
+        struct CountingAllocator;
+
+        thread_local! {
+            static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+        }
+
+        unsafe impl GlobalAlloc for CountingAllocator {
+            unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+                let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+                System.alloc(layout)
+            }
+
+            unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+                System.dealloc(ptr, layout)
+            }
+        }
+
+        #[global_allocator]
+        static ALLOC: CountingAllocator = CountingAllocator;
+
+        fn count_allocations<F: FnOnce()>(f: F) -> usize {
+            let before = ALLOCATIONS.with(|count| count.get());
+            f();
+            ALLOCATIONS.with(|count| count.get()) - before
+        }
+
+        #[test]
+        fn simulate_reuses_its_scratch_state() {
+            let mut rng = SmallRng::seed_from_u64(0);
+            let state = TicTacToe::new();
+            let mut region_wins = Vec::new();
+            let allocations = count_allocations(|| {
+                simulate(&mut rng, &state, 10_000, Scoring::default(), &mut region_wins, None);
+            });
+            assert!(allocations < 100, "simulate made {} allocations", allocations);
+        }
+
+        #[test]
+        fn expansion_allocates_only_the_children() {
+            let mut tree = ActionTree::new(TicTacToe::new());
+            let root = tree.root;
+            let allocations = count_allocations(|| {
+                tree.expand(root);
+            });
+
+            // Each child's state is cloned, but not boxed
+            let children = tree.root_state().available_actions().len();
+            assert_eq!(tree.node_count(), children + 1);
+            assert!(allocations < 3 * children, "expanding {} children made {} allocations", children, allocations);
+
+            // The children are the same states as playing each action on the root
+            for (action, child_id) in tree.ranked_actions() {
+                let mut expected = TicTacToe::new();
+                expected.do_action_mut(action);
+                assert_eq!(tree.nodes.get(child_id).unwrap().state, expected);
+            }
+        }
+    }
+}
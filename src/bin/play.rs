@@ -0,0 +1,138 @@
+//! Plays ultimate tic-tac-toe against the AI in the terminal, for trying out the engine without a browser.
+//!
+//! Usage: `cargo run --release --bin play -- [--sims N] [--human X|O|none] [--seed N]`
+//!
+//! Moves are entered as coordinate labels like "E5" (a letter from A to I for the sub-board, then a number from 1 to 9
+//! for the cell, both counted left to right and then top to bottom), or as action numbers from 0 to 80.
+
+use std::io::{ self, BufRead, Write };
+use uttt_ai::ai::{ ActionTree, Game };
+use uttt_ai::game::{ action_label, GameResult, Player, TicTacToe };
+
+const USAGE: &str = "usage: play [--sims N] [--human X|O|none] [--seed N]";
+
+struct Options {
+    // The number of simulations the AI runs before each of its moves
+    sims: u32,
+    // The player the human plays as, or None to watch the AI play itself
+    human: Option<Player>,
+    seed: u64
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options { sims: 10_000, human: Some(Player::X), seed: 0 };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().ok_or_else(|| format!("missing value for {}", arg))?;
+        match arg.as_str() {
+            "--sims" => options.sims = value.parse().map_err(|_| format!("invalid simulation count {}", value))?,
+            "--seed" => options.seed = value.parse().map_err(|_| format!("invalid seed {}", value))?,
+            "--human" => options.human = match value.to_ascii_uppercase().as_str() {
+                "X" => Some(Player::X),
+                "O" => Some(Player::O),
+                "NONE" => None,
+                _ => return Err(format!("invalid player {}", value))
+            },
+            _ => return Err(format!("unknown option {}", arg))
+        }
+    }
+    Ok(options)
+}
+
+// Reads a move written as a coordinate label (see `action_label`) or an action number
+fn parse_action(input: &str) -> Option<u8> {
+    if let Ok(action) = input.parse::<u8>() {
+        return Some(action).filter(|&action| action < 81);
+    }
+
+    let mut chars = input.chars();
+    let board_i = chars.next()?.to_ascii_uppercase() as u32;
+    let cell_i = chars.next()?.to_digit(10)?;
+    if chars.next().is_some() || !(b'A' as u32..=b'I' as u32).contains(&board_i) || !(1..=9).contains(&cell_i) {
+        return None;
+    }
+    Some(((board_i - b'A' as u32) * 9 + cell_i - 1) as u8)
+}
+
+// Draws the board as a grid of 'X', 'O' and '.' cells, with the sub-boards split by lines and labelled by letter
+fn render(board: &TicTacToe) -> String {
+    let mut text = String::new();
+    for row in 0..9u8 {
+        if row % 3 == 0 {
+            let letter = |col: u8| (b'A' + row / 3 * 3 + col) as char;
+            text += &format!("    {}       {}       {}\n", letter(0), letter(1), letter(2));
+        }
+        text += "  ";
+        for col in 0..9u8 {
+            if col > 0 && col % 3 == 0 {
+                text += "| ";
+            }
+            let action = (row / 3 * 3 + col / 3) * 9 + row % 3 * 3 + col % 3;
+            text.push(match board.cell_owner(action) {
+                Some(Player::X) => 'X',
+                Some(Player::O) => 'O',
+                None => '.'
+            });
+            text.push(' ');
+        }
+        text += "\n";
+        if row == 2 || row == 5 {
+            text += "  ------+-------+------\n";
+        }
+    }
+    text
+}
+
+fn main() {
+    let options = match parse_options() {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{}\n{}", error, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let mut tree = ActionTree::with_seed(TicTacToe::new(), options.seed);
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    while !tree.is_game_over() {
+        let board = tree.root_state();
+        println!("\n{}\n{}", render(board), board.describe());
+
+        let player = tree.current_player();
+        let action = if options.human == Some(player) {
+            let legal = board.available_actions().iter().map(|&action| action_label(action)).collect::<Vec<String>>();
+            println!("Legal moves: {}", legal.join(" "));
+            loop {
+                print!("{:?} to move: ", player);
+                io::stdout().flush().unwrap();
+                let line = match lines.next() {
+                    Some(Ok(line)) => line,
+                    // Stop quietly once there's no more input
+                    _ => return
+                };
+                match parse_action(line.trim()) {
+                    Some(action) if board.available_actions().contains(&action) => break action,
+                    Some(_) => println!("{} isn't a legal move.", line.trim()),
+                    None => println!("Enter a move like E5, or a number from 0 to 80.")
+                }
+            }
+        } else {
+            tree.do_search_sims(options.sims.max(1));
+            let action = tree.get_best_action()
+                .map(|(action, _)| *action)
+                .unwrap_or_else(|| tree.root_state().available_actions()[0]);
+            println!("{:?} plays {}", player, action_label(action));
+            action
+        };
+        tree.do_action(&action).expect("only legal moves are played");
+    }
+
+    let board = tree.root_state();
+    println!("\n{}", render(board));
+    match board.result() {
+        Some(GameResult::Win(winner)) => println!("Game over: {:?} wins", winner),
+        _ => println!("Game over: draw")
+    }
+}
@@ -0,0 +1,233 @@
+use wasm_bindgen::prelude::*;
+use web_sys::CanvasRenderingContext2d;
+use crate::ai::Game;
+use crate::game::Player;
+
+pub const COLUMNS: u8 = 7;
+pub const ROWS: u8 = 6;
+
+// Each column takes up ROWS + 1 bits of a bitboard, with an empty bit on top of each column so that lines can't wrap
+// around from the top of one column to the bottom of the next
+const COLUMN_BITS: u8 = ROWS + 1;
+
+const PLAYERS: [Player; 2] = [Player::X, Player::O];
+
+const BOARD_COLOR: &str = "#1e50c8";
+const EMPTY_COLOR: &str = "#fff";
+const X_COLOR: &str = "#e22";
+const O_COLOR: &str = "#ed2";
+
+// Checks whether a player's discs contain four in a row in any direction.
+fn has_four(board: u64) -> bool {
+    // Neighbouring cells are 1 bit apart vertically, a column apart horizontally, and one more or less than a column
+    // apart diagonally
+    let directions = [1, COLUMN_BITS, COLUMN_BITS - 1, COLUMN_BITS + 1];
+    directions.iter().any(|&shift| {
+        let pairs = board & (board >> shift);
+        pairs & (pairs >> (2 * shift)) != 0
+    })
+}
+
+/// Connect Four on a 7 column by 6 row board. Actions are the columns to drop a disc into, numbered from 0 on the left,
+/// and X moves first.
+#[derive(Clone, Debug)]
+pub struct ConnectFour {
+    // Bit `column * (ROWS + 1) + row` is set if the player has a disc in that cell, with row 0 at the bottom
+    board_x: u64,
+    board_o: u64,
+    // The number of discs in each column
+    heights: [u8; COLUMNS as usize],
+    available_actions: Vec<u8>,
+    current_player: Player,
+    game_over: bool,
+    // The player who won the game, which stays None if the game ended in a draw
+    winner: Option<Player>
+}
+
+impl ConnectFour {
+    pub fn new() -> Self {
+        ConnectFour {
+            board_x: 0,
+            board_o: 0,
+            heights: [0; COLUMNS as usize],
+            available_actions: (0..COLUMNS).collect(),
+            current_player: Player::X,
+            game_over: false,
+            winner: None
+        }
+    }
+
+    /// Returns the player whose disc is in a cell, if there is one there. Row 0 is the bottom row.
+    pub fn disc_at(&self, column: u8, row: u8) -> Option<Player> {
+        let cell_mask = 1u64 << (column * COLUMN_BITS + row);
+        if self.board_x & cell_mask != 0 {
+            Some(Player::X)
+        } else if self.board_o & cell_mask != 0 {
+            Some(Player::O)
+        } else {
+            None
+        }
+    }
+
+    /// Draws the board `width` pixels wide at the context's origin. The board is 6/7 as tall as it is wide.
+    pub fn draw(&self, ctx: &CanvasRenderingContext2d, width: f64) -> Result<(), JsValue> {
+        let cell_size = width / COLUMNS as f64;
+        ctx.set_fill_style_str(BOARD_COLOR);
+        ctx.fill_rect(0.0, 0.0, width, cell_size * ROWS as f64);
+
+        for column in 0..COLUMNS {
+            for row in 0..ROWS {
+                let color = match self.disc_at(column, row) {
+                    Some(Player::X) => X_COLOR,
+                    Some(Player::O) => O_COLOR,
+                    None => EMPTY_COLOR
+                };
+                ctx.set_fill_style_str(color);
+                ctx.begin_path();
+                ctx.arc(
+                    cell_size * (column as f64 + 0.5), cell_size * ((ROWS - 1 - row) as f64 + 0.5),
+                    cell_size / 2.0 * 0.8, 0.0, 2.0 * std::f64::consts::PI
+                )?;
+                ctx.fill();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Finds the column that was clicked if a disc can be dropped into it. The point is relative to the board's top left
+    /// corner, and `width` is the width the board was drawn with.
+    pub fn action_for_click(&self, x: f64, y: f64, width: f64) -> Option<u8> {
+        let cell_size = width / COLUMNS as f64;
+        if x < 0.0 || y < 0.0 || x >= width || y >= cell_size * ROWS as f64 {
+            return None;
+        }
+
+        let column = (x / cell_size).floor() as u8;
+        if self.available_actions.contains(&column) {
+            Some(column)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for ConnectFour {
+    fn default() -> Self {
+        ConnectFour::new()
+    }
+}
+
+impl Game for ConnectFour {
+    type Action = u8;
+    type Player = Player;
+
+    fn available_actions(&self) -> &[Self::Action] {
+        &self.available_actions
+    }
+
+    fn do_action_mut(&mut self, action: &Self::Action) {
+        let column = *action as usize;
+        let cell_mask = 1u64 << (action * COLUMN_BITS + self.heights[column]);
+        self.heights[column] += 1;
+        if self.heights[column] == ROWS {
+            self.available_actions.retain(|a| a != action);
+        }
+
+        let player_board = match self.current_player {
+            Player::X => {
+                self.board_x |= cell_mask;
+                self.board_x
+            },
+            Player::O => {
+                self.board_o |= cell_mask;
+                self.board_o
+            }
+        };
+
+        if has_four(player_board) {
+            self.winner = Some(self.current_player);
+            self.game_over = true;
+            self.available_actions.clear();
+            return;
+        }
+
+        self.current_player = match self.current_player {
+            Player::X => Player::O,
+            Player::O => Player::X
+        };
+        self.game_over = self.available_actions.is_empty();
+    }
+
+    fn get_players(&self) -> &[Self::Player] {
+        &PLAYERS
+    }
+
+    fn current_player(&self) -> Self::Player {
+        self.current_player
+    }
+
+    fn winner(&self) -> Option<Self::Player> {
+        self.winner
+    }
+
+    fn game_over(&self) -> bool {
+        self.game_over
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::ActionTree;
+
+    fn play(columns: &[u8]) -> ConnectFour {
+        let mut board = ConnectFour::new();
+        for column in columns {
+            board.do_action_mut(column);
+        }
+        board
+    }
+
+    fn best_drop(board: ConnectFour) -> Option<u8> {
+        let mut tree = ActionTree::new(board);
+        for _ in 0..2000 {
+            tree.do_search_step(10);
+        }
+        tree.get_best_action().map(|(action, _)| *action)
+    }
+
+    #[test]
+    fn four_in_a_row_wins_in_every_direction() {
+        // Horizontal, vertical and both diagonals
+        assert_eq!(play(&[0, 0, 1, 1, 2, 2, 3]).winner(), Some(Player::X));
+        assert_eq!(play(&[0, 1, 0, 1, 0, 1, 0]).winner(), Some(Player::X));
+        assert_eq!(play(&[0, 1, 1, 2, 2, 3, 2, 3, 3, 6, 3]).winner(), Some(Player::X));
+        assert_eq!(play(&[6, 5, 5, 4, 4, 3, 4, 3, 3, 0, 3]).winner(), Some(Player::X));
+
+        // Discs at the top of one column and the bottom of the next aren't in a line
+        let wrapped = play(&[1, 0, 1, 0, 0, 0, 0, 6, 0]);
+        assert_eq!((wrapped.disc_at(0, 5), wrapped.disc_at(1, 0)), (Some(Player::X), Some(Player::X)));
+        assert_eq!(wrapped.winner(), None);
+    }
+
+    #[test]
+    fn full_columns_cant_be_played() {
+        let board = play(&[0, 0, 0, 0, 0, 0]);
+        assert_eq!(board.available_actions(), &[1, 2, 3, 4, 5, 6]);
+        assert_eq!(board.action_for_click(10.0, 10.0, 700.0), None);
+        assert_eq!(board.action_for_click(150.0, 10.0, 700.0), Some(1));
+    }
+
+    #[test]
+    fn ai_takes_a_winning_drop() {
+        // X has three in a row along the bottom
+        assert_eq!(best_drop(play(&[0, 6, 1, 6, 2, 5])), Some(3));
+    }
+
+    #[test]
+    fn ai_blocks_a_losing_drop() {
+        // O has to stop X from completing the bottom row
+        assert_eq!(best_drop(play(&[0, 6, 1, 6, 2])), Some(3));
+    }
+}
@@ -1,17 +1,137 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{ Hash, Hasher };
+use rand::Rng;
+use rand::seq::IteratorRandom;
 use wasm_bindgen::prelude::*;
 use web_sys::CanvasRenderingContext2d;
-use crate::ai::Game;
+use crate::ai::{ Game, OpeningBook, Scoring };
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Player {
     X, O
 }
 
-#[derive(Clone, Debug)]
+/// An error produced when a game record can't be replayed. Each variant holds the index of the offending move.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RecordError {
+    /// The move isn't a number between 0 and 80
+    InvalidToken(usize),
+    /// The move isn't legal in the position it was played in
+    IllegalMove(usize)
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordError::InvalidToken(i) => write!(f, "move {} is not a valid action", i),
+            RecordError::IllegalMove(i) => write!(f, "move {} is not legal in its position", i)
+        }
+    }
+}
+
+/// An error produced when a list of moves can't be played, holding the index of the first move that isn't legal.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct InvalidMove(pub usize);
+
+impl fmt::Display for InvalidMove {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "move {} is not legal in its position", self.0)
+    }
+}
+
+/// An error produced when a position code can't be decoded.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ParseError {
+    /// The code isn't the right length
+    InvalidLength,
+    /// The character at this index isn't part of the code alphabet
+    InvalidCharacter(usize),
+    /// The code decodes to a position that can't come up in a real game
+    ImpossiblePosition
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::InvalidLength => write!(f, "position codes must be {} characters long", CODE_LENGTH),
+            ParseError::InvalidCharacter(i) => write!(f, "character {} is not valid in a position code", i),
+            ParseError::ImpossiblePosition => write!(f, "the position can't be reached in a game")
+        }
+    }
+}
+
+/// The state of a single sub-board.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SubStatus {
+    Empty,
+    InProgress,
+    Won(Player),
+    /// Every cell is filled but nobody has three in a row
+    Tied
+}
+
+/// How a finished game ended.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum GameResult {
+    Win(Player),
+    /// The game ended without a winner
+    Draw
+}
+
+/// What happens when a player is sent to a sub-board that has already been decided.
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SendToFullBoard {
+    /// Being sent to a sub-board that has been won or filled lets the player move in any sub-board that hasn't. These
+    /// are the usual rules.
+    Free,
+    /// Won sub-boards stay open, so a player sent to one still has to play there while it has empty cells. Only being
+    /// sent to a full sub-board lets the player move anywhere, in any empty cell.
+    Forced
+}
+
+/// The rules a game is played with, which are chosen before it starts and stay the same until it ends.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rules {
+    /// What happens when a player is sent to a sub-board that has already been decided
+    pub send_rule: SendToFullBoard,
+    /// Whether a game that ends without three sub-boards in a row is won by the player who won more sub-boards
+    pub subboard_tiebreak: bool,
+    /// The player who moves first
+    pub starter: Player
+}
+
+impl Default for Rules {
+    /// The usual rules, with X moving first.
+    fn default() -> Self {
+        Rules { send_rule: SendToFullBoard::Free, subboard_tiebreak: false, starter: Player::X }
+    }
+}
+
+/// A deterministic way of choosing moves, used to play games out reproducibly without an RNG.
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GreedyPolicy {
+    /// Always play the lowest-numbered available action
+    LowestIndex,
+    /// Play the lowest-numbered action that wins a sub-board, or the lowest-numbered action if none do
+    TakeSubboards
+}
+
+#[derive(Debug)]
 pub struct TicTacToe {
     // The current state of the game board
     board_x: u128,
     board_o: u128,
+    // The cells of each sub-board held by each player, kept alongside the bitboards so that checking a sub-board
+    // doesn't need to shift it out of them
+    subboards_x: [u16; 9],
+    subboards_o: [u16; 9],
     // Keeps track of which players have won which sub-boards
     winners_x: u16,
     winners_o: u16,
@@ -21,7 +141,96 @@ pub struct TicTacToe {
     available_actions: Vec<u8>,
     
     current_player: Player,
-    game_over: bool
+    game_over: bool,
+    // The player who won the game, which stays None if the game ended in a draw
+    winner: Option<Player>,
+    // The actions taken to reach this position, in order
+    history: Vec<u8>,
+    rules: Rules
+}
+
+// Clone is implemented by hand so that `clone_from` can reuse the allocation of the cached set of available actions,
+// which saves an allocation per simulation.
+impl Clone for TicTacToe {
+    fn clone(&self) -> Self {
+        TicTacToe {
+            available_actions: self.available_actions.clone(),
+            history: self.history.clone(),
+            ..*self
+        }
+    }
+
+    fn clone_from(&mut self, source: &Self) {
+        self.board_x = source.board_x;
+        self.board_o = source.board_o;
+        self.subboards_x = source.subboards_x;
+        self.subboards_o = source.subboards_o;
+        self.winners_x = source.winners_x;
+        self.winners_o = source.winners_o;
+        self.active_board = source.active_board;
+        self.available_actions.clone_from(&source.available_actions);
+        self.current_player = source.current_player;
+        self.game_over = source.game_over;
+        self.winner = source.winner;
+        self.history.clone_from(&source.history);
+        self.rules = source.rules;
+    }
+}
+
+// Two positions are equal if everything except the cached set of available actions matches, since the cache is derived
+// from the rest of the state. How the position was reached, including the moves and who moved first, doesn't matter.
+impl PartialEq for TicTacToe {
+    fn eq(&self, other: &Self) -> bool {
+        self.board_x == other.board_x
+            && self.board_o == other.board_o
+            && self.winners_x == other.winners_x
+            && self.winners_o == other.winners_o
+            && self.active_board == other.active_board
+            && self.current_player == other.current_player
+            && self.game_over == other.game_over
+            && self.rules.send_rule == other.rules.send_rule
+            && self.rules.subboard_tiebreak == other.rules.subboard_tiebreak
+    }
+}
+
+impl Eq for TicTacToe {}
+
+// Hashes the same fields that `eq` compares, so that equal positions hash equally however they were reached.
+impl Hash for TicTacToe {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.board_x.hash(state);
+        self.board_o.hash(state);
+        self.winners_x.hash(state);
+        self.winners_o.hash(state);
+        self.active_board.hash(state);
+        self.current_player.hash(state);
+        self.game_over.hash(state);
+        self.rules.send_rule.hash(state);
+        self.rules.subboard_tiebreak.hash(state);
+    }
+}
+
+// Formats the position as its 81 cells ('X', 'O' or '.', ordered by action index), the current player, and the active
+// sub-board ('-' if the current player can play anywhere), separated by spaces.
+impl fmt::Display for TicTacToe {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for i in 0..81 {
+            let cell_mask = 1u128 << i;
+            if self.board_x & cell_mask != 0 {
+                write!(f, "X")?;
+            } else if self.board_o & cell_mask != 0 {
+                write!(f, "O")?;
+            } else {
+                write!(f, ".")?;
+            }
+        }
+
+        write!(f, " {:?} ", self.current_player)?;
+        match self.active_board {
+            Some(board_i) => write!(f, "{}", board_i),
+            None => write!(f, "-")
+        }
+    }
 }
 
 const WIN_MASKS: [u16; 8] = [
@@ -35,16 +244,211 @@ const WIN_MASKS: [u16; 8] = [
     0b001010100
 ];
 
-// Checks whether a player has won a given board and if so returns that player.
+/// Generates a mask for every line of `k` cells in a row, horizontally, vertically or diagonally, on a `size` by `size`
+/// board. Cells are numbered row by row from the top left, so cell `row * size + column` is bit `row * size + column` of
+/// each mask. Boards can have at most 64 cells. `WIN_MASKS` holds the masks for `win_masks(3, 3)`.
+pub fn win_masks(size: usize, k: usize) -> Vec<u64> {
+    assert!(size * size <= 64, "boards can have at most 64 cells");
+    if k == 0 || k > size {
+        return Vec::new();
+    }
+
+    let directions: [(isize, isize); 4] = [(0, 1), (1, 0), (1, 1), (1, -1)];
+    let mut masks = Vec::new();
+    for row in 0..size as isize {
+        for column in 0..size as isize {
+            for &(row_step, column_step) in directions.iter() {
+                let cells = (0..k as isize)
+                    .map(|i| (row + i * row_step, column + i * column_step))
+                    .collect::<Vec<(isize, isize)>>();
+                let on_board = |&(r, c): &(isize, isize)| r < size as isize && c >= 0 && c < size as isize;
+                if cells.iter().all(on_board) {
+                    masks.push(cells.iter().fold(0, |mask, &(r, c)| mask | 1 << (r * size as isize + c)));
+                }
+            }
+        }
+    }
+    masks
+}
+
+// Positions are saved as their position codes, which hold everything except the cached actions (which are worked out
+// again when loading) and the move history (which can't be recovered, like for `from_code`). Loading checks that the
+// position is possible.
+#[cfg(feature = "serde")]
+impl serde::Serialize for TicTacToe {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_code())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for TicTacToe {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let code = <std::borrow::Cow<str>>::deserialize(deserializer)?;
+        TicTacToe::from_code(&code).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A game saved as the moves played from an empty board, along with its rules and who played each side. Unlike a
+/// saved position, it's small and can be replayed move by move, e.g. to review the game.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GameHistory {
+    pub moves: Vec<u8>,
+    /// Who played X, such as "human" or "ai", if known
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub x_player: Option<String>,
+    /// Who played O, if known
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub o_player: Option<String>,
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub rules: Rules
+}
+
+impl GameHistory {
+    /// Records the moves played on a board so far. Returns None for positions whose moves aren't known, like ones
+    /// loaded with `TicTacToe::from_code`.
+    pub fn of(board: &TicTacToe) -> Option<Self> {
+        if board.history.len() != (board.board_x | board.board_o).count_ones() as usize {
+            return None;
+        }
+
+        Some(GameHistory {
+            moves: board.history.clone(),
+            x_player: None,
+            o_player: None,
+            rules: board.rules
+        })
+    }
+
+    /// Plays the moves from an empty board with the saved rules, checking that each one is legal when it's played.
+    pub fn replay(&self) -> Result<TicTacToe, InvalidMove> {
+        TicTacToe::from_moves(&self.moves, self.rules)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl GameHistory {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("game histories can always be serialized")
+    }
+
+    /// Loads a history saved by `to_json`. The moves aren't checked until it's replayed.
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|err| err.to_string())
+    }
+}
+
+// Whether each of the 512 possible sets of cells a player can hold on a board contains three in a row. This is checked
+// after every move during simulations, so it's computed ahead of time.
+const WIN_TABLE: [bool; 512] = build_win_table();
+
+const fn build_win_table() -> [bool; 512] {
+    let mut table = [false; 512];
+    let mut board = 0;
+    while board < 512 {
+        let mut i = 0;
+        while i < WIN_MASKS.len() {
+            if WIN_MASKS[i] & !(board as u16) == 0 {
+                table[board] = true;
+            }
+            i += 1;
+        }
+        board += 1;
+    }
+    table
+}
+
+// Gets the first and last cell of a line from `WIN_MASKS`, which are the ends of the line drawn through it.
+fn mask_endpoints(mask: u16) -> (u8, u8) {
+    (mask.trailing_zeros() as u8, (15 - mask.leading_zeros()) as u8)
+}
+
+// Checks whether the cells a player holds on a board (the lowest 9 bits of `board`) contain three in a row.
 fn check_for_winner(board: u16) -> bool {
-    return WIN_MASKS.iter().any(|&mask| mask & board == mask)
+    WIN_TABLE[(board & 0x1FF) as usize]
 }
 
+// The most empty cells a position can have for `TicTacToe::count_leaves` to enumerate its completions.
+const MAX_COUNT_LEAVES_EMPTY_CELLS: u32 = 10;
+
+// Position codes are written in URL-safe base64, with each character holding 6 bits
+const CODE_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+// The width in bits of each field of a position code: both boards, both sets of sub-board winners, the active board
+// (15 if there isn't one), the current player, whether the game is over, whether won sub-boards stay open and whether
+// the sub-board tiebreak is used
+const CODE_FIELD_BITS: [u32; 9] = [81, 81, 9, 9, 4, 1, 1, 1, 1];
+const CODE_LENGTH: usize = 32;
+const NO_ACTIVE_BOARD: u128 = 15;
+
+// Names of the sub-boards, in order, for descriptions of the board
+const SUBBOARD_NAMES: [&str; 9] = [
+    "top-left", "top", "top-right", "left", "center", "right", "bottom-left", "bottom", "bottom-right"
+];
+
 const BLACK: &str = "#000";
 const RED: &str = "#f00";
 const BLUE: &str = "#00f";
 const LIGHT_RED: &str = "#fcc";
 const LIGHT_BLUE: &str = "#ccf";
+const LIGHT_YELLOW: &str = "#ff9";
+
+/// What to draw on top of the board itself. The options can be combined freely.
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DrawOptions {
+    /// Whether to highlight the cell of the last move
+    pub highlight_last_move: bool,
+    /// Whether to label each cell with its coordinates (see `action_label`)
+    pub show_labels: bool,
+    /// How much of the mark of the last move to draw, from 0 (none of it) to 1 (all of it), for animating it
+    pub last_move_progress: f64
+}
+
+impl Default for DrawOptions {
+    /// Just the board, with nothing highlighted or labelled.
+    fn default() -> Self {
+        DrawOptions { highlight_last_move: false, show_labels: false, last_move_progress: 1.0 }
+    }
+}
+
+#[wasm_bindgen]
+impl DrawOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new(highlight_last_move: bool, show_labels: bool, last_move_progress: f64) -> Self {
+        DrawOptions { highlight_last_move, show_labels, last_move_progress }
+    }
+}
+
+/// The colors used to draw the board, as CSS color strings.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Theme {
+    /// The lines of the grids
+    pub grid: String,
+    /// X's marks and winning lines
+    pub x: String,
+    /// O's marks and winning lines
+    pub o: String,
+    /// The background of the sub-boards X can play in
+    pub x_highlight: String,
+    /// The background of the sub-boards O can play in
+    pub o_highlight: String,
+    /// The background of the cell of the last move
+    pub last_move: String
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            grid: BLACK.to_owned(),
+            x: RED.to_owned(),
+            o: BLUE.to_owned(),
+            x_highlight: LIGHT_RED.to_owned(),
+            o_highlight: LIGHT_BLUE.to_owned(),
+            last_move: LIGHT_YELLOW.to_owned()
+        }
+    }
+}
 
 fn line(ctx: &CanvasRenderingContext2d, x1: f64, y1: f64, x2: f64, y2: f64) {
     ctx.begin_path();
@@ -63,68 +467,698 @@ fn draw_grid(ctx: &CanvasRenderingContext2d, grid_size: f64) {
     line(ctx, 0.0, 2.0 * cell_size, grid_size, 2.0 * cell_size);
 }
 
-fn draw_x(ctx: &CanvasRenderingContext2d, size: f64) {
+// Clamps how far along an animation is to between 0 (not started) and 1 (finished). NaN counts as finished, so that a
+// bad value still draws the whole board.
+fn animation_progress(progress: f64) -> f64 {
+    if progress.is_nan() {
+        1.0
+    } else {
+        progress.clamp(0.0, 1.0)
+    }
+}
+
+// Draws an X centered on the origin. Only the first `progress` (from 0 to 1) of its strokes are drawn, one stroke after
+// the other, so that a new mark can be animated.
+fn draw_x(ctx: &CanvasRenderingContext2d, size: f64, theme: &Theme, progress: f64) {
     let offset = size / 2.0 * 0.8;
-    ctx.set_stroke_style(&RED.into());
-    line(ctx, -offset, -offset, offset, offset);
-    line(ctx, offset, -offset, -offset, offset);
+    let first = (progress * 2.0).min(1.0);
+    let second = (progress * 2.0 - 1.0).max(0.0);
+    ctx.set_stroke_style_str(&theme.x);
+    if first > 0.0 {
+        line(ctx, -offset, -offset, offset * (2.0 * first - 1.0), offset * (2.0 * first - 1.0));
+    }
+    if second > 0.0 {
+        line(ctx, offset, -offset, -offset * (2.0 * second - 1.0), offset * (2.0 * second - 1.0));
+    }
 }
 
-fn draw_o(ctx: &CanvasRenderingContext2d, size: f64) -> Result<(), JsValue> {
-    ctx.set_stroke_style(&BLUE.into());
+// Draws an O centered on the origin, like `draw_x`. The circle is drawn clockwise from the top.
+fn draw_o(ctx: &CanvasRenderingContext2d, size: f64, theme: &Theme, progress: f64) -> Result<(), JsValue> {
+    if progress <= 0.0 {
+        return Ok(());
+    }
+
+    let start = -std::f64::consts::FRAC_PI_2;
+    ctx.set_stroke_style_str(&theme.o);
     ctx.begin_path();
-    ctx.arc(0.0, 0.0, size / 2.0 * 0.8, 0.0, 2.0 * std::f64::consts::PI)?;
+    ctx.arc(0.0, 0.0, size / 2.0 * 0.8, start, start + 2.0 * std::f64::consts::PI * progress)?;
     ctx.stroke();
     Ok(())
 }
 
+// Draws a dash centered on the origin, to mark a sub-board that nobody won.
+fn draw_dash(ctx: &CanvasRenderingContext2d, size: f64, theme: &Theme) {
+    let offset = size / 2.0 * 0.5;
+    ctx.set_stroke_style_str(&theme.grid);
+    line(ctx, -offset, 0.0, offset, 0.0);
+}
+
+// Strokes a line through every three in a row among the cells a player holds on a board of the given size, with the
+// context translated to the board's top left corner.
+fn draw_winning_lines(ctx: &CanvasRenderingContext2d, board: u16, size: f64) {
+    let cell_size = size / 3.0;
+    let center = |cell: u8| (cell_size * ((cell % 3) as f64 + 0.5), cell_size * ((cell / 3) as f64 + 0.5));
+    for &mask in WIN_MASKS.iter().filter(|&&mask| mask & !board == 0) {
+        let (first, last) = mask_endpoints(mask);
+        let ((x1, y1), (x2, y2)) = (center(first), center(last));
+        line(ctx, x1, y1, x2, y2);
+    }
+}
+
+/// Gets the coordinate label for an action: a letter from A to I for its sub-board, counting left to right and then top
+/// to bottom, followed by a number from 1 to 9 for its cell, counted the same way. For example, the center cell of the
+/// center sub-board (action 40) is "E5".
+pub fn action_label(action: u8) -> String {
+    format!("{}{}", (b'A' + action / 9) as char, action % 9 + 1)
+}
+
+// Writes each cell's label in small, faded text in the cell's top left corner, on a board of the given size.
+fn draw_labels(ctx: &CanvasRenderingContext2d, size: f64, theme: &Theme) -> Result<(), JsValue> {
+    let cell_size = size / 9.0;
+    ctx.save();
+    ctx.set_global_alpha(0.4);
+    ctx.set_fill_style_str(&theme.grid);
+    ctx.set_font(&format!("{}px sans-serif", (cell_size / 5.0).round()));
+    ctx.set_text_baseline("top");
+    let padding = cell_size / 16.0;
+    let mut result = Ok(());
+    for action in 0..81 {
+        let (cell_x, cell_y) = cell_origin(action, size);
+        result = result.and_then(|_| ctx.fill_text(&action_label(action), cell_x + padding, cell_y + padding));
+    }
+    ctx.restore();
+    result
+}
+
+// Gets the transform from the CSS pixels the board is laid out in to the canvas pixels it's drawn in, as the arguments
+// (a, b, c, d, e, f) to `CanvasRenderingContext2d::transform`.
+fn css_to_canvas_transform(pixel_ratio: f64) -> [f64; 6] {
+    [pixel_ratio, 0.0, 0.0, pixel_ratio, 0.0, 0.0]
+}
+
+// Gets the position of the top left corner of the cell for an action on a board of the given size.
+fn cell_origin(action: u8, size: f64) -> (f64, f64) {
+    let (board_i, cell_i) = (action / 9, action % 9);
+    let cell_size = size / 9.0;
+    (
+        size / 3.0 * (board_i % 3) as f64 + cell_size * (cell_i % 3) as f64,
+        size / 3.0 * (board_i / 3) as f64 + cell_size * (cell_i / 3) as f64
+    )
+}
+
 impl TicTacToe {
     pub fn new() -> Self {
+        TicTacToe::with_rules(Rules::default())
+    }
+
+    /// Creates an empty board for a game played with the given rules.
+    pub fn with_rules(rules: Rules) -> Self {
         let mut board = TicTacToe {
             board_x: 0,
             board_o: 0,
+            subboards_x: [0; 9],
+            subboards_o: [0; 9],
             winners_x: 0,
             winners_o: 0,
             active_board: None,
             available_actions: Vec::new(),
-            current_player: Player::X,
-            game_over: false
+            current_player: rules.starter,
+            game_over: false,
+            winner: None,
+            history: Vec::new(),
+            rules
         };
         board.update_available_actions();
         board
     }
 
-    pub fn update_available_actions(&mut self) {
-        //self.available_actions.clear();
+    /// Gets the player who moved first.
+    pub fn starter(&self) -> Player {
+        self.rules.starter
+    }
+
+    /// Creates an empty board for a new game with the same rules and first player as this one.
+    pub fn restart(&self) -> Self {
+        TicTacToe::with_rules(self.rules)
+    }
+
+    /// Replays a game record, which is a list of actions separated by whitespace or commas (e.g. "40 36 4"), starting
+    /// from an empty board with the usual rules.
+    pub fn from_game_record(record: &str) -> Result<Self, RecordError> {
+        TicTacToe::from_game_record_with_rules(record, Rules::default())
+    }
+
+    /// Replays a game record like `from_game_record`, for a game played with the given rules.
+    pub fn from_game_record_with_rules(record: &str, rules: Rules) -> Result<Self, RecordError> {
+        TicTacToe::replay_game_record(record, rules, |_| {})
+    }
+
+    /// Plays a list of actions starting from an empty board with the given rules, checking that each one is legal when
+    /// it's played.
+    pub fn from_moves(actions: &[u8], rules: Rules) -> Result<Self, InvalidMove> {
+        let mut board = TicTacToe::with_rules(rules);
+        for (i, action) in actions.iter().enumerate() {
+            if !board.available_actions.contains(action) {
+                return Err(InvalidMove(i));
+            }
+            board.do_action_mut(action);
+        }
+        Ok(board)
+    }
+
+    /// Replays a game record played with the given rules, calling `visit` with the starting position and with the
+    /// position after each move.
+    pub fn replay_game_record<F>(record: &str, rules: Rules, mut visit: F) -> Result<Self, RecordError>
+    where F: FnMut(&TicTacToe) {
+        let mut board = TicTacToe::with_rules(rules);
+        visit(&board);
+
+        let tokens = record.split(|c: char| c.is_whitespace() || c == ',').filter(|t| !t.is_empty());
+        for (i, token) in tokens.enumerate() {
+            let action = token.parse::<u8>().map_err(|_| RecordError::InvalidToken(i))?;
+            if action >= 81 {
+                return Err(RecordError::InvalidToken(i));
+            }
+            if !board.available_actions.contains(&action) {
+                return Err(RecordError::IllegalMove(i));
+            }
+            board.do_action_mut(&action);
+            visit(&board);
+        }
+        Ok(board)
+    }
+
+    /// The built-in opening book, which opens in the center of the center board.
+    pub fn opening_book() -> OpeningBook<u8> {
+        let mut book = OpeningBook::new();
+        book.insert(TicTacToe::new().to_code(), 40);
+        book
+    }
+
+    /// Encodes the position as a short, URL-safe string that `from_code` turns back into the same position.
+    pub fn to_code(&self) -> String {
+        let fields = [
+            self.board_x,
+            self.board_o,
+            self.winners_x as u128,
+            self.winners_o as u128,
+            self.active_board.map_or(NO_ACTIVE_BOARD, |board_i| board_i as u128),
+            (self.current_player == Player::O) as u128,
+            self.game_over as u128,
+            (self.rules.send_rule == SendToFullBoard::Forced) as u128,
+            self.rules.subboard_tiebreak as u128
+        ];
+
+        let mut bits = Vec::with_capacity(CODE_LENGTH * 6);
+        for (&value, &width) in fields.iter().zip(CODE_FIELD_BITS.iter()) {
+            bits.extend((0..width).map(|i| value >> i & 1 == 1));
+        }
+        bits.chunks(6)
+            .map(|chunk| {
+                let digit = chunk.iter().rev().fold(0, |digit, &bit| digit << 1 | bit as usize);
+                CODE_ALPHABET[digit] as char
+            })
+            .collect()
+    }
+
+    /// Decodes a position written by `to_code`, rejecting codes for positions that can't come up in a game.
+    pub fn from_code(code: &str) -> Result<Self, ParseError> {
+        if code.len() != CODE_LENGTH {
+            return Err(ParseError::InvalidLength);
+        }
+
+        let mut bits = Vec::with_capacity(CODE_LENGTH * 6);
+        for (i, c) in code.bytes().enumerate() {
+            let digit = CODE_ALPHABET.iter().position(|&d| d == c).ok_or(ParseError::InvalidCharacter(i))?;
+            bits.extend((0..6).map(|bit| digit >> bit & 1 == 1));
+        }
+
+        let mut bits = bits.into_iter();
+        let mut fields = CODE_FIELD_BITS.iter().map(|&width| {
+            (0..width).fold(0u128, |value, i| value | (bits.next().unwrap() as u128) << i)
+        });
+        let mut next_field = || fields.next().unwrap();
+
+        let board_x = next_field();
+        let board_o = next_field();
+        let winners_x = next_field() as u16;
+        let winners_o = next_field() as u16;
+        let active_board = match next_field() {
+            NO_ACTIVE_BOARD => None,
+            board_i if board_i < 9 => Some(board_i as u8),
+            _ => return Err(ParseError::ImpossiblePosition)
+        };
+        let current_player = if next_field() == 1 { Player::O } else { Player::X };
+        let game_over = next_field() == 1;
+        let send_rule = if next_field() == 1 { SendToFullBoard::Forced } else { SendToFullBoard::Free };
+        let subboard_tiebreak = next_field() == 1;
+
+        let mut board = TicTacToe {
+            board_x,
+            board_o,
+            subboards_x: [0; 9],
+            subboards_o: [0; 9],
+            winners_x,
+            winners_o,
+            active_board,
+            available_actions: Vec::new(),
+            current_player,
+            game_over: false,
+            winner: None,
+            history: Vec::new(),
+            rules: Rules { send_rule, subboard_tiebreak, starter: Player::X }
+        };
+        board.sync_subboards();
+        board.update_available_actions();
+
+        if let Some(starter) = board.possible_starter(game_over) {
+            board.rules.starter = starter;
+            board.game_over = game_over;
+            if game_over && check_for_winner(board.winners_of(current_player)) {
+                board.winner = Some(current_player);
+            } else if game_over {
+                board.winner = board.tiebreak_winner();
+            }
+            board.update_available_actions();
+            Ok(board)
+        } else {
+            Err(ParseError::ImpossiblePosition)
+        }
+    }
+
+    /// Checks that a decoded position could have come up in a game that is over if and only if `game_over` is set, and
+    /// returns the player who must have moved first to reach it. The position's available actions must already have
+    /// been worked out as if the game weren't over.
+    fn possible_starter(&self, game_over: bool) -> Option<Player> {
+        if !self.is_possible(game_over) {
+            return None;
+        }
+
+        // The players take turns, so the starter has either made one more move or it's their turn again. A won game
+        // stops with the winner, who made the last move, as the current player.
+        let other = |player: Player| if player == Player::X { Player::O } else { Player::X };
+        let won = check_for_winner(self.winners_x) || check_for_winner(self.winners_o);
+        Some(match self.board_x.count_ones().cmp(&self.board_o.count_ones()) {
+            Ordering::Greater => Player::X,
+            Ordering::Less => Player::O,
+            Ordering::Equal if won => other(self.current_player),
+            Ordering::Equal => self.current_player
+        })
+    }
+
+    /// Checks that a decoded position could have come up in a game that is over if and only if `game_over` is set.
+    /// The position's available actions must already have been worked out as if the game weren't over.
+    fn is_possible(&self, game_over: bool) -> bool {
+        // Only the 81 cells and 9 sub-boards exist, and nobody can own the same one as someone else
+        if (self.board_x | self.board_o) >> 81 != 0 || self.board_x & self.board_o != 0 {
+            return false;
+        }
+        if (self.winners_x | self.winners_o) >> 9 != 0 || self.winners_x & self.winners_o != 0 {
+            return false;
+        }
+
+        // A sub-board is won exactly when one player has three in a row on it. Under the forced rule, won sub-boards
+        // stay open, so the other player can get three in a row on one later without taking it.
+        for board_i in 0..9 {
+            let line_x = check_for_winner(self.subboards_x[board_i]);
+            let line_o = check_for_winner(self.subboards_o[board_i]);
+            let won_x = self.winners_x & 1 << board_i != 0;
+            let won_o = self.winners_o & 1 << board_i != 0;
+            let consistent = match self.rules.send_rule {
+                SendToFullBoard::Free => line_x == won_x && line_o == won_o,
+                SendToFullBoard::Forced => {
+                    (won_x && line_x) || (won_o && line_o) || !(won_x || won_o || line_x || line_o)
+                }
+            };
+            if !consistent {
+                return false;
+            }
+        }
+
+        // Either player can move first, and then the players take turns, so one of them has made as many moves as the
+        // other or one more. If they've made the same number of moves, either of them could have moved last.
+        let moves_x = self.board_x.count_ones();
+        let moves_o = self.board_o.count_ones();
+        let could_have_moved_last = |player: Player| match moves_x.cmp(&moves_o) {
+            Ordering::Greater => player == Player::X,
+            Ordering::Less => player == Player::O,
+            Ordering::Equal => moves_x > 0
+        };
+        if moves_x.max(moves_o) > moves_x.min(moves_o) + 1 {
+            return false;
+        }
+
+        let won_x = check_for_winner(self.winners_x);
+        let won_o = check_for_winner(self.winners_o);
+        if won_x || won_o {
+            // The game stops as soon as someone wins, leaving the winner as the current player
+            game_over && could_have_moved_last(self.current_player) && won_x == (self.current_player == Player::X)
+        } else {
+            // Otherwise the turn has passed to the other player, and the game is only over if they can't move
+            let other = if self.current_player == Player::X { Player::O } else { Player::X };
+            let active_board_open = self.active_board.is_none_or(|board_i| match self.rules.send_rule {
+                SendToFullBoard::Free => !self.is_decided(board_i),
+                SendToFullBoard::Forced => !self.is_subboard_full(board_i)
+            });
+            (moves_x == moves_o || could_have_moved_last(other))
+                && active_board_open
+                && game_over == self.available_actions.is_empty()
+        }
+    }
+
+    /// Gets the sub-board the current player has to play in, or None if they can play in any open sub-board.
+    pub fn active_board(&self) -> Option<u8> {
+        self.active_board
+    }
+
+    /// Gets how the game ended, or None if it's still going. Unlike `winner`, this tells a draw apart from a game that
+    /// isn't over yet.
+    pub fn result(&self) -> Option<GameResult> {
+        if !self.game_over {
+            return None;
+        }
+        Some(self.winner.map_or(GameResult::Draw, GameResult::Win))
+    }
+
+    /// Gets the rules this game is played with.
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    /// Gets the rule for sending players to decided sub-boards that this game uses.
+    pub fn send_rule(&self) -> SendToFullBoard {
+        self.rules.send_rule
+    }
+
+    /// Sets whether a game that ends without either player getting three sub-boards in a row is won by the player who
+    /// won more sub-boards, instead of being a draw. Like the rule for sending players, this should be set before the
+    /// game starts, and isn't included in game records.
+    pub fn set_subboard_tiebreak(&mut self, enabled: bool) {
+        self.rules.subboard_tiebreak = enabled;
+    }
+
+    /// Gets whether games that fill up without a winner are decided by the number of sub-boards won.
+    pub fn subboard_tiebreak(&self) -> bool {
+        self.rules.subboard_tiebreak
+    }
+
+    /// Gets the winner of a game that has filled up without three sub-boards in a row: the player who won more
+    /// sub-boards if the tiebreak is used, and otherwise nobody.
+    fn tiebreak_winner(&self) -> Option<Player> {
+        if !self.rules.subboard_tiebreak {
+            return None;
+        }
+        let (x_boards, o_boards) = self.subboards_won();
+        match x_boards.cmp(&o_boards) {
+            Ordering::Greater => Some(Player::X),
+            Ordering::Less => Some(Player::O),
+            Ordering::Equal => None
+        }
+    }
+
+    /// Fills in the cells of each sub-board from the bitboards, after they've been set directly.
+    fn sync_subboards(&mut self) {
+        for board_i in 0..9 {
+            self.subboards_x[board_i] = (self.board_x >> (9 * board_i) & 0x1FF) as u16;
+            self.subboards_o[board_i] = (self.board_o >> (9 * board_i) & 0x1FF) as u16;
+        }
+    }
+
+    /// Checks whether every cell of a sub-board has been played in.
+    fn is_subboard_full(&self, board_i: u8) -> bool {
+        self.subboards_x[board_i as usize] | self.subboards_o[board_i as usize] == 0x1FF
+    }
+
+    /// Checks whether a sub-board has been won or filled, so that nothing can change who owns it.
+    fn is_decided(&self, board_i: u8) -> bool {
+        self.is_subboard_full(board_i) || (self.winners_x | self.winners_o) & 1 << board_i != 0
+    }
+
+    /// Returns the set of sub-boards that the given player has won.
+    fn winners_of(&self, player: Player) -> u16 {
+        match player {
+            Player::X => self.winners_x,
+            Player::O => self.winners_o
+        }
+    }
 
+    /// Takes back the last action by replaying the game from the start up to the action before it. Returns false if
+    /// there is nothing to undo, which includes positions loaded with `from_code`, since their moves aren't known.
+    pub fn undo(&mut self) -> bool {
+        let moves = (self.board_x | self.board_o).count_ones() as usize;
+        if self.history.is_empty() || self.history.len() != moves {
+            return false;
+        }
+
+        let mut board = self.restart();
+        for action in &self.history[..moves - 1] {
+            board.do_action_mut(action);
+        }
+        *self = board;
+        true
+    }
+
+    /// Returns the number of legal moves the current player can choose from.
+    pub fn active_branching(&self) -> u32 {
+        self.available_actions.len() as u32
+    }
+
+    pub fn update_available_actions(&mut self) {
         if self.game_over {
             // no possible actions if someone has already won
+            self.available_actions.clear();
             return;
         }
 
         let available_spaces = !(self.board_x | self.board_o);
-        let available_subboards = !(self.winners_x | self.winners_o);
+        let available_subboards = match self.rules.send_rule {
+            SendToFullBoard::Free => !(self.winners_x | self.winners_o),
+            SendToFullBoard::Forced => !0
+        };
+
+        // Being sent to a full sub-board, whether it was won or tied, is the same as being sent to a decided one
+        let active_board = self.active_board.filter(|&board_i| available_spaces >> (9 * board_i) & 0x1FF != 0);
 
-        self.available_actions = if let Some(board_i) = self.active_board {
+        // Refill the cached actions in place so that their allocation is reused
+        self.available_actions.clear();
+        if let Some(board_i) = active_board {
             let board_start = board_i * 9;
-            (board_start..board_start + 9)
-                .filter(|&i| available_spaces & (1 << i) != 0)
-                .collect()
+            self.available_actions.extend((board_start..board_start + 9)
+                .filter(|&i| available_spaces & (1 << i) != 0));
         } else {
-            (0..81)
+            self.available_actions.extend((0..81)
                 .filter(|&i| available_subboards & (1 << (i / 9)) != 0)
-                .filter(|&i| available_spaces & (1 << i) != 0)
-                .collect()
+                .filter(|&i| available_spaces & (1 << i) != 0));
+        }
+    }
+
+    /// Checks whether there are no cells left that can be played in, ignoring whether the game has been won. Under the
+    /// usual rules that's when every sub-board has been won or filled, and otherwise it's when every cell is filled.
+    fn is_full(&self) -> bool {
+        match self.rules.send_rule {
+            SendToFullBoard::Free => (0..9).all(|board_i| self.is_decided(board_i)),
+            SendToFullBoard::Forced => (self.board_x | self.board_o).count_ones() == 81
+        }
+    }
+
+    /// Counts the empty cells in sub-boards that haven't been won yet, which bounds the number of moves left in the game.
+    fn empty_cells(&self) -> u32 {
+        let empty_spaces = !(self.board_x | self.board_o);
+        let decided_subboards = match self.rules.send_rule {
+            SendToFullBoard::Free => self.winners_x | self.winners_o,
+            SendToFullBoard::Forced => 0
+        };
+        (0..9)
+            .filter(|&board_i| decided_subboards & (1 << board_i) == 0)
+            .map(|board_i| (empty_spaces >> (9 * board_i) & 0x1FF).count_ones())
+            .sum()
+    }
+
+    /// Exhaustively counts the number of ways the game can be played to the end from this position. Returns None if
+    /// the position has too many empty cells for this to finish in a reasonable amount of time.
+    pub fn count_leaves(&self) -> Option<u64> {
+        if self.empty_cells() > MAX_COUNT_LEAVES_EMPTY_CELLS {
+            None
+        } else {
+            Some(self.count_leaves_unchecked())
+        }
+    }
+
+    fn count_leaves_unchecked(&self) -> u64 {
+        if self.game_over {
+            return 1;
         }
+
+        self.available_actions.iter()
+            .map(|action| self.do_action(action).count_leaves_unchecked())
+            .sum()
     }
 
-    // Draws the board onto an HTML canvas with the upper-left corner at (0, 0).
-    pub fn draw(&self, ctx: &CanvasRenderingContext2d, size: f64) -> Result<(), JsValue> {
+    /// Classifies the sub-board with the given index.
+    pub fn subboard_status(&self, board_i: u8) -> SubStatus {
+        let board_mask = 1 << board_i;
+        if self.winners_x & board_mask != 0 {
+            return SubStatus::Won(Player::X);
+        }
+        if self.winners_o & board_mask != 0 {
+            return SubStatus::Won(Player::O);
+        }
+
+        match self.subboards_x[board_i as usize] | self.subboards_o[board_i as usize] {
+            0 => SubStatus::Empty,
+            0x1FF => SubStatus::Tied,
+            _ => SubStatus::InProgress
+        }
+    }
+
+    /// Returns the player who has played in the cell for the given action, if anyone has.
+    pub fn cell_owner(&self, action: u8) -> Option<Player> {
+        let cell_mask = 1u128 << action;
+        if self.board_x & cell_mask != 0 {
+            Some(Player::X)
+        } else if self.board_o & cell_mask != 0 {
+            Some(Player::O)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the number of sub-boards won by X and by O.
+    pub fn subboards_won(&self) -> (u32, u32) {
+        (self.winners_x.count_ones(), self.winners_o.count_ones())
+    }
+
+    /// Returns the indices of the sub-boards that are full without either player winning them.
+    pub fn drawn_subboards(&self) -> Vec<u8> {
+        (0..9)
+            .filter(|&board_i| self.subboard_status(board_i) == SubStatus::Tied)
+            .collect()
+    }
+
+    /// Describes the position in words, such as "It's X's turn. X must play in the center sub-board. Sub-board 0
+    /// (top-left) won by O.", for screen readers and debugging.
+    pub fn describe(&self) -> String {
+        let mut sentences = Vec::new();
+
+        if self.game_over {
+            match self.winner {
+                Some(winner) => sentences.push(format!("The game is over. {:?} won.", winner)),
+                None => sentences.push("The game is over. It ended in a draw.".to_string())
+            }
+        } else {
+            let player = self.current_player;
+            sentences.push(format!("It's {:?}'s turn.", player));
+            match self.active_board {
+                Some(board_i) => sentences.push(format!(
+                    "{:?} must play in the {} sub-board.", player, SUBBOARD_NAMES[board_i as usize]
+                )),
+                None => sentences.push(format!("{:?} can play in any open sub-board.", player))
+            }
+        }
+
+        for board_i in 0..9 {
+            let name = SUBBOARD_NAMES[board_i as usize];
+            match self.subboard_status(board_i) {
+                SubStatus::Won(player) => {
+                    sentences.push(format!("Sub-board {} ({}) won by {:?}.", board_i, name, player))
+                }
+                SubStatus::Tied => sentences.push(format!("Sub-board {} ({}) tied.", board_i, name)),
+                SubStatus::Empty | SubStatus::InProgress => {}
+            }
+        }
+
+        sentences.join(" ")
+    }
+
+    /// Checks whether an action would win its sub-board for the current player.
+    fn wins_subboard(&self, action: u8) -> bool {
+        self.completes_line(self.current_player, action)
+    }
+
+    /// Checks whether a player would get three in a row on a sub-board that hasn't been won yet by playing in the cell
+    /// for the given action.
+    fn completes_line(&self, player: Player, action: u8) -> bool {
+        let board_i = action / 9;
+        let subboard = match player {
+            Player::X => self.subboards_x[board_i as usize],
+            Player::O => self.subboards_o[board_i as usize]
+        } | 1 << (action % 9);
+        (self.winners_x | self.winners_o) & 1 << board_i == 0 && check_for_winner(subboard)
+    }
+
+    /// Plays the game to the end, choosing every move with the given policy, and returns the winner.
+    pub fn play_out_greedy(&mut self, policy: GreedyPolicy) -> Option<Player> {
+        while let Some(&lowest) = self.available_actions.first() {
+            let action = match policy {
+                GreedyPolicy::LowestIndex => lowest,
+                GreedyPolicy::TakeSubboards => self.available_actions.iter()
+                    .copied()
+                    .find(|&action| self.wins_subboard(action))
+                    .unwrap_or(lowest)
+            };
+            self.do_action_mut(&action);
+        }
+        self.winner()
+    }
+
+    /// Draws the board at the context's origin. `size` is in CSS pixels, and everything drawn is scaled up by
+    /// `pixel_ratio` (normally `window.devicePixelRatio`) so that the board is sharp on high-DPI screens when the canvas
+    /// is that many times larger than its CSS size. Any transform already set on the context applies first, so it has
+    /// to be in canvas pixels.
+    pub fn draw(&self, ctx: &CanvasRenderingContext2d, size: f64, theme: &Theme, pixel_ratio: f64) -> Result<(), JsValue> {
+        self.draw_board(ctx, size, theme, pixel_ratio, &DrawOptions::default())
+    }
+
+    /// Draws the board like `draw`, but also highlights the cell of the last move.
+    pub fn draw_with_last_move(
+        &self, ctx: &CanvasRenderingContext2d, size: f64, theme: &Theme, pixel_ratio: f64
+    ) -> Result<(), JsValue> {
+        let options = DrawOptions { highlight_last_move: true, ..DrawOptions::default() };
+        self.draw_board(ctx, size, theme, pixel_ratio, &options)
+    }
+
+    /// Draws the board like `draw_with_last_move`, with the mark of the last move only partly drawn, so that it can be
+    /// animated by drawing frames with `last_move_progress` going from 0 (not drawn yet) to 1 (fully drawn). Everything
+    /// else is drawn as usual.
+    pub fn draw_animated(
+        &self, ctx: &CanvasRenderingContext2d, size: f64, theme: &Theme, pixel_ratio: f64, last_move_progress: f64
+    ) -> Result<(), JsValue> {
+        let options = DrawOptions { highlight_last_move: true, last_move_progress, ..DrawOptions::default() };
+        self.draw_with_options(ctx, size, theme, pixel_ratio, &options)
+    }
+
+    /// Draws the board like `draw`, with any combination of the extras in `options`, such as labelling each cell with
+    /// its coordinates so that players can tell each other about moves.
+    pub fn draw_with_options(
+        &self, ctx: &CanvasRenderingContext2d, size: f64, theme: &Theme, pixel_ratio: f64, options: &DrawOptions
+    ) -> Result<(), JsValue> {
+        let options = DrawOptions { last_move_progress: animation_progress(options.last_move_progress), ..*options };
+        self.draw_board(ctx, size, theme, pixel_ratio, &options)
+    }
+
+    /// Returns the last action taken, or None if no moves have been made since the board was created.
+    pub fn last_action(&self) -> Option<u8> {
+        self.history.last().copied()
+    }
+
+    fn draw_board(
+        &self, ctx: &CanvasRenderingContext2d, size: f64, theme: &Theme, pixel_ratio: f64, options: &DrawOptions
+    ) -> Result<(), JsValue> {
+        ctx.save();
+        let [a, b, c, d, e, f] = css_to_canvas_transform(pixel_ratio);
+        let result = ctx.transform(a, b, c, d, e, f).and_then(|_| self.draw_unscaled(ctx, size, theme, options));
+        ctx.restore();
+        result
+    }
+
+    fn draw_unscaled(
+        &self, ctx: &CanvasRenderingContext2d, size: f64, theme: &Theme, options: &DrawOptions
+    ) -> Result<(), JsValue> {
         // Highlight the active sub-board.
         if !self.game_over {
             match self.current_player {
-                Player::X => ctx.set_fill_style(&LIGHT_RED.into()),
-                Player::O => ctx.set_fill_style(&LIGHT_BLUE.into())
+                Player::X => ctx.set_fill_style_str(&theme.x_highlight),
+                Player::O => ctx.set_fill_style_str(&theme.o_highlight)
             }
             
             if let Some(i) = self.active_board {
@@ -141,8 +1175,19 @@ impl TicTacToe {
             }
         }
 
+        // Highlight the cell of the last move.
+        if let Some(action) = self.last_action().filter(|_| options.highlight_last_move) {
+            let (cell_x, cell_y) = cell_origin(action, size);
+            ctx.set_fill_style_str(&theme.last_move);
+            ctx.fill_rect(cell_x, cell_y, size / 9.0, size / 9.0);
+        }
+
+        if options.show_labels {
+            draw_labels(ctx, size, theme)?;
+        }
+
         // Draw large board.
-        ctx.set_stroke_style(&BLACK.into());
+        ctx.set_stroke_style_str(&theme.grid);
         ctx.set_line_width(6.0);
         draw_grid(ctx, size);
 
@@ -156,7 +1201,7 @@ impl TicTacToe {
             ctx.save();
             ctx.translate(board_x, board_y)?;
 
-            ctx.set_stroke_style(&BLACK.into());
+            ctx.set_stroke_style_str(&theme.grid);
             draw_grid(ctx, board_size);
 
             for cell_i in 0..9 {
@@ -166,21 +1211,34 @@ impl TicTacToe {
                 ctx.save();
                 ctx.translate(cell_x + cell_size / 2.0, cell_y + cell_size / 2.0)?;
 
-                let cell_mask = 1u128 << (cell_i + board_i * 9);
+                let action = cell_i + board_i * 9;
+                let progress = if self.last_action() == Some(action) { options.last_move_progress } else { 1.0 };
+                let cell_mask = 1u128 << action;
                 if self.board_x & cell_mask != 0 {
-                    draw_x(ctx, cell_size);
+                    draw_x(ctx, cell_size, theme, progress);
                 }
                 if self.board_o & cell_mask != 0 {
-                    draw_o(ctx, cell_size)?;
+                    draw_o(ctx, cell_size, theme, progress)?;
                 }
 
                 ctx.restore();
             }
 
+            // Draw lines through the cells that won the board.
+            let board_mask = 1u16 << board_i;
+            if self.winners_x & board_mask != 0 {
+                ctx.set_stroke_style_str(&theme.x);
+                draw_winning_lines(ctx, (self.board_x >> (9 * board_i) & 0x1FF) as u16, board_size);
+            }
+            if self.winners_o & board_mask != 0 {
+                ctx.set_stroke_style_str(&theme.o);
+                draw_winning_lines(ctx, (self.board_o >> (9 * board_i) & 0x1FF) as u16, board_size);
+            }
+
             ctx.restore();
         }
 
-        // Draw symbols for winners over boards they've won.
+        // Draw symbols for winners over boards they've won, and a dash over boards that were tied.
         ctx.set_line_width(6.0);
         for board_i in 0..9 {
             let board_x = board_size * (board_i % 3) as f64;
@@ -189,20 +1247,34 @@ impl TicTacToe {
             ctx.save();
             ctx.translate(board_x + board_size / 2.0, board_y + board_size / 2.0)?;
             
-            let cell_mask = 1u16 << board_i;
-            if self.winners_x & cell_mask != 0 {
-                draw_x(ctx, board_size);
-            }
-            if self.winners_o & cell_mask != 0 {
-                draw_o(ctx, board_size)?;
+            match self.subboard_status(board_i) {
+                SubStatus::Won(Player::X) => draw_x(ctx, board_size, theme, 1.0),
+                SubStatus::Won(Player::O) => draw_o(ctx, board_size, theme, 1.0)?,
+                SubStatus::Tied => draw_dash(ctx, board_size, theme),
+                SubStatus::Empty | SubStatus::InProgress => {}
             }
 
             ctx.restore();
         }
 
+        // Draw lines through the sub-boards that won the game.
+        match self.winner {
+            Some(Player::X) => {
+                ctx.set_stroke_style_str(&theme.x);
+                draw_winning_lines(ctx, self.winners_x, size);
+            }
+            Some(Player::O) => {
+                ctx.set_stroke_style_str(&theme.o);
+                draw_winning_lines(ctx, self.winners_o, size);
+            }
+            None => {}
+        }
+
         Ok(())
     }
 
+    /// Finds the legal action whose cell contains a point. The point and `board_size` are in CSS pixels relative to the
+    /// board's top left corner, the same units `draw` takes its size in, regardless of the pixel ratio it's drawn with.
     pub fn action_for_click(&mut self, x: f64, y: f64, board_size: f64) -> Option<u8> {
         let cell_x = x * 9.0 / board_size;
         let cell_y = y * 9.0 / board_size;
@@ -224,6 +1296,12 @@ impl TicTacToe {
     }
 }
 
+impl Default for TicTacToe {
+    fn default() -> Self {
+        TicTacToe::new()
+    }
+}
+
 const TIC_TAC_TOE_PLAYERS: [Player; 2] = [Player::X, Player::O];
 
 impl Game for TicTacToe {
@@ -234,32 +1312,28 @@ impl Game for TicTacToe {
         &self.available_actions
     }
 
-    fn do_action(&self, action: &Self::Action) -> Box<Self> {
-        let mut c = self.clone();
-        c.do_action_mut(action);
-        Box::new(c)
-    }
-
     fn do_action_mut(&mut self, action: &Self::Action) {
         let board_i = *action / 9;
         let cell_i = *action % 9;
+        self.history.push(*action);
 
         // Put the symbol on the board
-        let player_board = match self.current_player {
+        let subboard = match self.current_player {
             Player::X => {
                 self.board_x |= 1u128 << action;
-                self.board_x
+                self.subboards_x[board_i as usize] |= 1 << cell_i;
+                self.subboards_x[board_i as usize]
             },
             Player::O => {
                 self.board_o |= 1u128 << action;
-                self.board_o
+                self.subboards_o[board_i as usize] |= 1 << cell_i;
+                self.subboards_o[board_i as usize]
             }
         };
 
-        // Check if this causes the current player to win this board
-        // Isolate the specific subboard the action modified
-        let subboard = player_board >> (9 * board_i);
-        if check_for_winner((subboard & 0x1FF) as u16) {
+        // Check if this causes the current player to win this board, unless it was already won
+        let already_won = (self.winners_x | self.winners_o) & 1 << board_i != 0;
+        if !already_won && check_for_winner(subboard) {
             let winner_board = match self.current_player {
                 Player::X => {
                     self.winners_x |= 1u16 << board_i;
@@ -274,19 +1348,19 @@ impl Game for TicTacToe {
             // Check if this causes the current player to win the game
             if check_for_winner(winner_board) {
                 self.game_over = true;
-                self.current_player = self.current_player;
+                self.winner = Some(self.current_player);
                 self.update_available_actions();
                 return;
             }
         }
 
-        // Set the active board
-        let board_mask = 1 << board_i;
-        if (self.winners_x | self.winners_o) & board_mask != 0 {
-            self.active_board = Some(cell_i);
-        } else {
-            self.active_board = None;
-        }
+        // Set the active board. The next player has to play in the sub-board matching the cell that was just played in,
+        // unless that sub-board is full, or has already been won under the usual rules.
+        let freed = match self.rules.send_rule {
+            SendToFullBoard::Free => self.is_decided(cell_i),
+            SendToFullBoard::Forced => self.is_subboard_full(cell_i)
+        };
+        self.active_board = if freed { None } else { Some(cell_i) };
 
         // Toggle player
         self.current_player = match self.current_player {
@@ -294,13 +1368,59 @@ impl Game for TicTacToe {
             Player::O => Player::X
         };
 
+        // Check if there's nowhere left to play, which makes the game a draw, or won on sub-boards if that rule is being
+        // used. This is worked out from the bitboards, so that the actions don't need to be listed to see that there
+        // aren't any.
+        if self.is_full() {
+            self.game_over = true;
+            self.winner = self.tiebreak_winner();
+        }
+
         // Update set of available actions
         self.update_available_actions();
+    }
 
-        // Check if the game is a draw (no available actions)
-        if self.available_actions.is_empty() {
-            self.game_over = true;
-        }
+    // Plays a move that wins a sub-board if there is one, otherwise blocks the opponent from winning a sub-board if
+    // possible, and otherwise plays randomly. This makes simulations look more like real games than purely random play.
+    fn rollout_action<R: Rng>(&self, rng: &mut R) -> Option<Self::Action> {
+        let opponent = match self.current_player {
+            Player::X => Player::O,
+            Player::O => Player::X
+        };
+
+        let actions = self.available_actions.iter().copied();
+        actions.clone().filter(|&action| self.wins_subboard(action)).choose(rng)
+            .or_else(|| actions.clone().filter(|&action| self.completes_line(opponent, action)).choose(rng))
+            .or_else(|| actions.choose(rng))
+    }
+
+    // Splits the points for a win by how many sub-boards each player has won, counting one extra for each so that the
+    // split is even when neither has won any. This is much quicker than playing the game out, but ignores everything
+    // about the sub-boards that are still open.
+    fn evaluate<R: Rng>(&self, _rng: &mut R, scoring: Scoring) -> HashMap<Self::Player, u32> {
+        let (x_points, o_points) = match (self.game_over, self.winner) {
+            (true, Some(Player::X)) => (scoring.win, 0),
+            (true, Some(Player::O)) => (0, scoring.win),
+            (true, None) => (scoring.draw, scoring.draw),
+            (false, _) => {
+                let (x_boards, o_boards) = self.subboards_won();
+                let (x_boards, o_boards) = (x_boards + 1, o_boards + 1);
+                let x_points = scoring.win * x_boards / (x_boards + o_boards);
+                (x_points, scoring.win - x_points)
+            }
+        };
+        [(Player::X, x_points), (Player::O, o_points)].iter().copied().collect()
+    }
+
+    // Favors the center of each sub-board, then the corners, since those cells are part of the most lines
+    fn action_priors(&self) -> HashMap<Self::Action, f64> {
+        let weight = |action: u8| match action % 9 {
+            4 => 3.0,
+            0 | 2 | 6 | 8 => 2.0,
+            _ => 1.0
+        };
+        let total = self.available_actions.iter().map(|&action| weight(action)).sum::<f64>();
+        self.available_actions.iter().map(|&action| (action, weight(action) / total)).collect()
     }
 
     fn get_players(&self) -> &[Self::Player] {
@@ -312,14 +1432,718 @@ impl Game for TicTacToe {
     }
 
     fn winner(&self) -> Option<Self::Player> {
-        if self.game_over {
-            Some(self.current_player)
+        self.winner
+    }
+
+    fn game_over(&self) -> bool {
+        self.game_over
+    }
+
+    fn region_count(&self) -> usize {
+        9
+    }
+
+    fn region_winner(&self, region: usize) -> Option<Self::Player> {
+        if self.winners_x & (1 << region) != 0 {
+            Some(Player::X)
+        } else if self.winners_o & (1 << region) != 0 {
+            Some(Player::O)
         } else {
             None
         }
     }
 
-    fn game_over(&self) -> bool {
-        self.game_over
+    fn position_key(&self) -> Option<String> {
+        Some(self.to_code())
+    }
+
+    fn is_initial_state(&self) -> bool {
+        self.board_x | self.board_o == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Builds a position from nine sub-boards, each written as a string of 'X', 'O' and '.' cells
+    fn position(subboards: [&str; 9], current_player: Player, active_board: Option<u8>) -> TicTacToe {
+        let mut board = TicTacToe::new();
+        for (board_i, subboard) in subboards.iter().enumerate() {
+            for (cell_i, cell) in subboard.chars().enumerate() {
+                let mask = 1u128 << (board_i * 9 + cell_i);
+                match cell {
+                    'X' => board.board_x |= mask,
+                    'O' => board.board_o |= mask,
+                    _ => {}
+                }
+            }
+            board.sync_subboards();
+            if check_for_winner((board.board_x >> (9 * board_i) & 0x1FF) as u16) {
+                board.winners_x |= 1 << board_i;
+            }
+            if check_for_winner((board.board_o >> (9 * board_i) & 0x1FF) as u16) {
+                board.winners_o |= 1 << board_i;
+            }
+        }
+        board.current_player = current_player;
+        board.active_board = active_board;
+        board.update_available_actions();
+        board
+    }
+
+    impl TicTacToe {
+        // Works out the legal moves again from the board alone, without going through `update_available_actions`, and
+        // checks that the cached moves match
+        fn recompute_and_check(&self) {
+            let occupied = self.board_x | self.board_o;
+            let has_space = |board_i: u8| occupied >> (9 * board_i) & 0x1FF != 0x1FF;
+            let won = |board_i: u8| (self.winners_x | self.winners_o) & 1 << board_i != 0;
+            let expected = (0..81u8)
+                .filter(|_| !self.game_over)
+                .filter(|&action| occupied & 1 << action == 0)
+                .filter(|&action| match self.active_board.filter(|&board_i| has_space(board_i)) {
+                    Some(board_i) => action / 9 == board_i,
+                    None => self.rules.send_rule == SendToFullBoard::Forced || !won(action / 9)
+                })
+                .collect::<Vec<u8>>();
+            assert_eq!(self.available_actions, expected, "stale actions for {}", self);
+
+            for board_i in 0..9 {
+                assert_eq!(self.subboards_x[board_i], (self.board_x >> (9 * board_i) & 0x1FF) as u16);
+                assert_eq!(self.subboards_o[board_i], (self.board_o >> (9 * board_i) & 0x1FF) as u16);
+            }
+        }
+    }
+
+    #[test]
+    fn sending_to_a_tied_subboard_grants_a_free_move() {
+        // The top-left sub-board is full without a winner
+        let tied = "XOXXOOOXX";
+        for &rule in [SendToFullBoard::Free, SendToFullBoard::Forced].iter() {
+            let mut board = position([tied, "", "", "", "", "", "", "", ""], Player::X, Some(4));
+            board.rules.send_rule = rule;
+            board.do_action_mut(&36);
+            assert_eq!(board.active_board, None);
+            assert!(!board.game_over());
+            assert_eq!(board.available_actions().len(), 71);
+        }
+    }
+
+    #[test]
+    fn subboard_tiebreak_decides_full_meta_board() {
+        // Neither player has three sub-boards in a row. X has won four sub-boards and O has won four, and X is about to
+        // play the last move on the bottom-right sub-board.
+        let x = "XXX......";
+        let o = "OOO......";
+        let subboards = |last: &'static str| [x, o, x, x, o, o, o, x, last];
+
+        // Winning the last sub-board gives X five sub-boards to O's four
+        for &tiebreak in [false, true].iter() {
+            let mut board = position(subboards("XX.OOXOXO"), Player::X, Some(8));
+            board.set_subboard_tiebreak(tiebreak);
+            board.do_action_mut(&74);
+            assert!(board.game_over());
+            assert_eq!(board.winner(), if tiebreak { Some(Player::X) } else { None });
+        }
+
+        // Tying the last sub-board leaves the count even, so the game is still a draw
+        let mut board = position(subboards(".OXXOOOXX"), Player::X, Some(8));
+        board.set_subboard_tiebreak(true);
+        board.do_action_mut(&72);
+        assert!(board.game_over());
+        assert_eq!(board.winner(), None);
+    }
+
+    #[test]
+    fn action_labels_name_subboard_and_cell() {
+        assert_eq!(action_label(0), "A1");
+        assert_eq!(action_label(8), "A9");
+        assert_eq!(action_label(9), "B1");
+        assert_eq!(action_label(40), "E5");
+        assert_eq!(action_label(80), "I9");
+        let labels = (0..81).map(action_label).collect::<std::collections::HashSet<String>>();
+        assert_eq!(labels.len(), 81);
+    }
+
+    #[test]
+    fn full_active_subboard_frees_the_player() {
+        // The player has been sent to the tied top-left sub-board, which has no empty cells
+        let board = position(["XOXXOOOXX", "X........", "", "", "", "", "", "", ""], Player::O, Some(0));
+        assert!(!board.available_actions().is_empty());
+        assert_eq!(board.available_actions().len(), 71);
+        assert!(board.available_actions().iter().all(|&action| action >= 9));
+    }
+
+    #[test]
+    fn forced_rule_keeps_won_subboards_open() {
+        let won = "XXX......";
+        let mut free = position([won, "", "", "", "", "", "", "", ""], Player::O, Some(4));
+        free.do_action_mut(&36);
+        assert_eq!(free.active_board, None);
+
+        let mut forced = position([won, "", "", "", "", "", "", "", ""], Player::O, Some(4));
+        forced.rules.send_rule = SendToFullBoard::Forced;
+        forced.update_available_actions();
+        forced.do_action_mut(&36);
+        assert_eq!(forced.active_board, Some(0));
+        assert_eq!(forced.available_actions(), &[3, 4, 5, 6, 7, 8]);
+
+        // Three in a row on a sub-board that was already won doesn't change who owns it
+        let mut forced = position(["XX.OOO...", "", "", "", "", "", "", "", ""], Player::X, Some(0));
+        forced.rules.send_rule = SendToFullBoard::Forced;
+        forced.do_action_mut(&2);
+        assert_eq!(forced.subboard_status(0), SubStatus::Won(Player::O));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn from_game_record_replays_moves() {
+        let board = TicTacToe::from_game_record("40, 36 4").unwrap();
+        assert_eq!(board.board_x, (1 << 40) | (1 << 4));
+        assert_eq!(board.board_o, 1 << 36);
+        assert_eq!(board.current_player(), Player::O);
+        assert_eq!(board.active_board, Some(4));
+    }
+
+    #[test]
+    fn from_game_record_reports_bad_moves() {
+        assert_eq!(TicTacToe::from_game_record("40 4x").unwrap_err(), RecordError::InvalidToken(1));
+        assert_eq!(TicTacToe::from_game_record("40 81").unwrap_err(), RecordError::InvalidToken(1));
+        // After 40 the next move has to be played in sub-board 4
+        assert_eq!(TicTacToe::from_game_record("40 0").unwrap_err(), RecordError::IllegalMove(1));
+    }
+
+    #[test]
+    fn count_leaves_enumerates_endgames() {
+        // Three empty cells, each of which sends the opponent to the won sub-board 1 and so grants a free move. None of
+        // them can complete a line, so every order of filling them is a separate game.
+        let board = position([
+            "X.OOXXXOO", "XXXOO....", "X.OOXXXOO",
+            "X.OOXXXOO", "XOXXOOOXX", "XOXXOOOXX",
+            "XOXXOOOXX", "XOXXOOOXX", "XOXXOOOXX"
+        ], Player::X, None);
+        assert_eq!(board.count_leaves(), Some(6));
+    }
+
+    #[test]
+    fn count_leaves_refuses_early_positions() {
+        assert_eq!(TicTacToe::new().count_leaves(), None);
+    }
+
+    #[test]
+    fn play_out_greedy_is_reproducible() {
+        for &policy in [GreedyPolicy::LowestIndex, GreedyPolicy::TakeSubboards].iter() {
+            let start = TicTacToe::from_game_record("40 36 4").unwrap();
+            let mut a = start.clone();
+            let mut b = start.clone();
+            let winner = a.play_out_greedy(policy);
+            assert_eq!(winner, b.play_out_greedy(policy));
+            assert!(a.game_over());
+            assert_eq!((a.board_x, a.board_o), (b.board_x, b.board_o));
+        }
+    }
+
+    #[test]
+    fn drawn_subboards_lists_only_tied_subboards() {
+        let board = position([
+            "XOXXOOOXX", "XXXOO....", "X.OOXXXOO",
+            ".........", ".........", ".........",
+            ".........", ".........", "OXOOXXXOO"
+        ], Player::X, None);
+        assert_eq!(board.drawn_subboards(), vec![0, 8]);
+    }
+
+    #[test]
+    fn subboard_status_classifies_each_subboard() {
+        let board = position([
+            "XOXXOOOXX", "XXXOO....", "X.OOXXXOO",
+            ".........", "OOOXX.X..", ".........",
+            ".........", ".........", "........."
+        ], Player::X, None);
+        let statuses = (0..5).map(|board_i| board.subboard_status(board_i)).collect::<Vec<SubStatus>>();
+        assert_eq!(statuses, vec![
+            SubStatus::Tied, SubStatus::Won(Player::X), SubStatus::InProgress, SubStatus::Empty, SubStatus::Won(Player::O)
+        ]);
+    }
+
+    #[test]
+    fn transposed_move_orders_are_the_same_position() {
+        use std::collections::hash_map::DefaultHasher;
+
+        fn hash_of(board: &TicTacToe) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            board.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let board = TicTacToe::from_game_record("1 9 2 18").unwrap();
+        let transposed = TicTacToe::from_game_record("2 18 1 9").unwrap();
+        assert_eq!(board, transposed);
+        assert_eq!(hash_of(&board), hash_of(&transposed));
+
+        // Equal positions are merged in hashed collections, but a different active sub-board is a different position
+        let other = TicTacToe::from_game_record("1 9 2 19").unwrap();
+        let positions = [board, transposed, other].iter().cloned().collect::<std::collections::HashSet<TicTacToe>>();
+        assert_eq!(positions.len(), 2);
+    }
+
+    #[test]
+    fn evaluate_splits_points_by_subboards_won() {
+        use rand::SeedableRng;
+        use rand::rngs::SmallRng;
+
+        let mut rng = SmallRng::seed_from_u64(0);
+        let scoring = Scoring::default();
+        let even = TicTacToe::new().evaluate(&mut rng, scoring);
+        assert_eq!((even[&Player::X], even[&Player::O]), (5, 5));
+
+        // X has won two sub-boards and O none
+        let board = position([
+            "XXX.OO...", "XXXO.O...", ".........",
+            ".........", ".........", ".........",
+            ".........", ".........", "........."
+        ], Player::O, None);
+        let points = board.evaluate(&mut rng, scoring);
+        assert_eq!((points[&Player::X], points[&Player::O]), (7, 3));
+    }
+
+    #[test]
+    fn result_separates_draws_from_unfinished_games() {
+        use rand::SeedableRng;
+        use rand::rngs::SmallRng;
+
+        assert_eq!(TicTacToe::new().result(), None);
+
+        // Play random games until there's been a win for each player and a draw
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut results = std::collections::HashSet::new();
+        while results.len() < 3 {
+            let mut board = TicTacToe::new();
+            while let Some(action) = board.rollout_action(&mut rng) {
+                assert_eq!(board.result(), None);
+                board.do_action_mut(&action);
+            }
+            let result = board.result().unwrap();
+            assert_eq!(result, board.winner().map_or(GameResult::Draw, GameResult::Win));
+            results.insert(result);
+        }
+        assert!(results.contains(&GameResult::Win(Player::X)) && results.contains(&GameResult::Win(Player::O)));
+        assert!(results.contains(&GameResult::Draw));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn game_history_round_trips_through_json() {
+        let rules = Rules { send_rule: SendToFullBoard::Forced, subboard_tiebreak: true, starter: Player::X };
+        let mut board = TicTacToe::with_rules(rules);
+        for &action in [40, 36, 4, 37, 13].iter() {
+            board.do_action_mut(&action);
+        }
+
+        let mut history = GameHistory::of(&board).unwrap();
+        history.x_player = Some("human".to_owned());
+        let loaded = GameHistory::from_json(&history.to_json()).unwrap();
+        assert_eq!(loaded, history);
+        let replayed = loaded.replay().unwrap();
+        assert_eq!(replayed, board);
+        assert_eq!(replayed.send_rule(), SendToFullBoard::Forced);
+        assert!(replayed.subboard_tiebreak());
+
+        // Moves are only checked when the history is replayed
+        let rules_json = r#""send_rule":"Free","subboard_tiebreak":false,"starter":"X""#;
+        let illegal = GameHistory::from_json(&format!(r#"{{"moves":[40,36,4,36],{}}}"#, rules_json)).unwrap();
+        assert_eq!(illegal.replay(), Err(InvalidMove(3)));
+        assert!(GameHistory::from_json(&format!(r#"{{"moves":[40,"x"],{}}}"#, rules_json)).is_err());
+
+        // The moves of a position loaded from a code aren't known
+        assert_eq!(GameHistory::of(&TicTacToe::from_code(&board.to_code()).unwrap()), None);
+    }
+
+    #[test]
+    fn animation_progress_is_clamped() {
+        assert_eq!(animation_progress(0.25), 0.25);
+        assert_eq!(animation_progress(-1.0), 0.0);
+        assert_eq!(animation_progress(3.0), 1.0);
+        assert_eq!(animation_progress(f64::INFINITY), 1.0);
+        assert_eq!(animation_progress(f64::NAN), 1.0);
+    }
+
+    #[test]
+    fn filled_meta_board_is_a_draw() {
+        // X has won the top left sub-board and O the center, and every other sub-board is tied or about to be
+        let subboards = [
+            "XXXOO.O..", "XOXXOOOXX", "XOXXOOOXX",
+            "XOXXOOOXX", "OOOXX.X..", "XOXXOOOXX",
+            "XOXXOOOXX", "XOXXOOOXX", "XOXXOOOX."
+        ];
+        let mut board = position(subboards, Player::X, None);
+        assert_eq!(board.available_actions(), &[80]);
+        board.do_action_mut(&80);
+        assert!(board.game_over());
+        assert_eq!(board.result(), Some(GameResult::Draw));
+        assert!(board.available_actions().is_empty());
+
+        // Won sub-boards can still be played in when they stay open, so the game goes on
+        let mut board = position(subboards, Player::X, None);
+        board.rules.send_rule = SendToFullBoard::Forced;
+        board.update_available_actions();
+        board.do_action_mut(&80);
+        assert_eq!(board.result(), None);
+        assert_eq!(board.available_actions(), &[5, 7, 8, 41, 43, 44]);
+    }
+
+    #[test]
+    fn subboards_won_counts_each_player() {
+        assert_eq!(TicTacToe::new().subboards_won(), (0, 0));
+
+        let board = position([
+            "XXX......", "X...X...X", ".........",
+            ".........", "OOO......", ".........",
+            ".........", ".........", "........."
+        ], Player::X, None);
+        assert_eq!(board.subboards_won(), (2, 1));
+    }
+
+    #[test]
+    fn display_shows_cells_player_and_active_board() {
+        let board = TicTacToe::from_game_record("40 36").unwrap();
+        let expected = format!("{}O{}X{} X 0", ".".repeat(36), "...", ".".repeat(40));
+        assert_eq!(board.to_string(), expected);
+        assert_eq!(TicTacToe::new().to_string(), format!("{} X -", ".".repeat(81)));
+    }
+
+    #[test]
+    fn winner_is_none_after_a_draw() {
+        // Every way of filling the last three cells leaves the board full without a winner
+        let mut board = position([
+            "X.OOXXXOO", "XXXOO....", "X.OOXXXOO",
+            "X.OOXXXOO", "XOXXOOOXX", "XOXXOOOXX",
+            "XOXXOOOXX", "XOXXOOOXX", "XOXXOOOXX"
+        ], Player::X, None);
+        assert_eq!(board.play_out_greedy(GreedyPolicy::LowestIndex), None);
+        assert!(board.game_over());
+        assert_eq!(board.winner(), None);
+    }
+
+    #[test]
+    fn describe_mentions_turn_constraint_and_sub_boards() {
+        // X has taken the top row of the center sub-board and sent O to the top-right one
+        let board = TicTacToe::from_game_record("36 4 37 13 38").unwrap();
+        assert_eq!(
+            board.describe(),
+            "It's O's turn. O must play in the top-right sub-board. Sub-board 4 (center) won by X."
+        );
+
+        // Being sent to the won center sub-board lets O play anywhere
+        let board = TicTacToe::from_game_record("36 4 37 13 38 20 22").unwrap();
+        assert!(board.describe().contains("O can play in any open sub-board."));
+
+        let mut board = TicTacToe::from_game_record("36 4 37 13").unwrap();
+        board.play_out_greedy(GreedyPolicy::TakeSubboards);
+        let description = board.describe();
+        assert!(description.starts_with(&format!("The game is over. {:?} won.", board.winner().unwrap())));
+        assert!(!description.contains("turn"));
+    }
+
+    #[test]
+    fn winner_is_set_after_a_win() {
+        let mut board = TicTacToe::from_game_record("36 4 37 13").unwrap();
+        board.play_out_greedy(GreedyPolicy::TakeSubboards);
+        assert!(board.game_over());
+        assert!(board.winner().is_some());
+    }
+
+    // The mask-based check that the lookup table replaced
+    fn check_for_winner_by_masks(board: u16) -> bool {
+        WIN_MASKS.iter().any(|&mask| mask & !board == 0)
+    }
+
+    #[test]
+    fn win_table_matches_masks() {
+        for board in 0..512 {
+            assert_eq!(check_for_winner(board), check_for_winner_by_masks(board), "board {:09b}", board);
+        }
+    }
+
+    #[test]
+    fn rollout_action_takes_subboards() {
+        use rand::SeedableRng;
+        use rand::rngs::SmallRng;
+        use rand::seq::SliceRandom;
+
+        // Counts how many of 1000 playouts end with X owning sub-board 4, where X has two in a row and is about to move
+        fn subboard_wins<F: FnMut(&TicTacToe, &mut SmallRng) -> Option<u8>>(mut policy: F) -> u32 {
+            let mut rng = SmallRng::seed_from_u64(0);
+            let start = TicTacToe::from_game_record("36 4 37 13").unwrap();
+            (0..1000)
+                .filter(|_| {
+                    let mut board = start.clone();
+                    while let Some(action) = policy(&board, &mut rng) {
+                        board.do_action_mut(&action);
+                    }
+                    board.region_winner(4) == Some(Player::X)
+                })
+                .count() as u32
+        }
+
+        let heuristic = subboard_wins(|board, rng| board.rollout_action(rng));
+        let random = subboard_wins(|board, rng| board.available_actions().choose(rng).copied());
+        assert!(heuristic > random, "heuristic won {} times, random won {} times", heuristic, random);
+    }
+
+    #[test]
+    fn codes_round_trip_random_positions() {
+        use rand::SeedableRng;
+        use rand::rngs::SmallRng;
+        use rand::seq::SliceRandom;
+
+        let mut rng = SmallRng::seed_from_u64(0);
+        for game in 0..500 {
+            let send_rule = if game % 2 == 0 { SendToFullBoard::Free } else { SendToFullBoard::Forced };
+            let rules = Rules { send_rule, subboard_tiebreak: game % 4 >= 2, ..Rules::default() };
+            let mut board = TicTacToe::with_rules(rules);
+            let moves = rng.gen_range(0, 81);
+            for _ in 0..moves {
+                match board.available_actions().choose(&mut rng) {
+                    Some(&action) => board.do_action_mut(&action),
+                    None => break
+                }
+            }
+
+            let code = board.to_code();
+            assert_eq!(code.len(), CODE_LENGTH);
+            assert!(code.bytes().all(|c| CODE_ALPHABET.contains(&c)));
+            let decoded = TicTacToe::from_code(&code).unwrap();
+            assert_eq!(decoded, board, "code {} didn't round trip", code);
+            assert_eq!(decoded.winner(), board.winner());
+            assert_eq!(decoded.available_actions(), board.available_actions());
+        }
+    }
+
+    #[test]
+    fn cached_actions_stay_consistent() {
+        use rand::SeedableRng;
+        use rand::rngs::SmallRng;
+        use rand::seq::SliceRandom;
+
+        let mut rng = SmallRng::seed_from_u64(5);
+        for game in 0..400 {
+            let send_rule = if game % 2 == 0 { SendToFullBoard::Free } else { SendToFullBoard::Forced };
+            let mut board = TicTacToe::with_rules(Rules {
+                send_rule,
+                subboard_tiebreak: game % 4 >= 2,
+                ..Rules::default()
+            });
+            board.recompute_and_check();
+            while let Some(&action) = board.available_actions().choose(&mut rng) {
+                board.do_action_mut(&action);
+                board.recompute_and_check();
+                board.do_action(&action).recompute_and_check();
+
+                if rng.gen_range(0, 10) == 0 {
+                    let mut undone = board.clone();
+                    assert!(undone.undo());
+                    undone.recompute_and_check();
+                }
+            }
+
+            TicTacToe::from_code(&board.to_code()).unwrap().recompute_and_check();
+            #[cfg(feature = "serde")]
+            serde_json::from_str::<TicTacToe>(&serde_json::to_string(&board).unwrap()).unwrap().recompute_and_check();
+        }
+    }
+
+    #[test]
+    fn codes_keep_the_rules() {
+        // O is sent to the top left sub-board, which X has already won, but it stays open under the forced rule
+        let rules = Rules { send_rule: SendToFullBoard::Forced, subboard_tiebreak: true, ..Rules::default() };
+        let forced = TicTacToe::from_game_record_with_rules("4 36 0 3 27 5 45 6 62 72 8 73 9", rules).unwrap();
+        assert_eq!(forced.subboard_status(0), SubStatus::Won(Player::X));
+        let loaded = TicTacToe::from_code(&forced.to_code()).unwrap();
+        assert_eq!(loaded, forced);
+        assert_eq!(loaded.rules(), rules);
+        assert_eq!(loaded.available_actions(), &[1, 2, 7]);
+    }
+
+    #[test]
+    fn from_code_rejects_impossible_positions() {
+        assert_eq!(TicTacToe::from_code("AAAA"), Err(ParseError::InvalidLength));
+        let mut code = TicTacToe::new().to_code();
+        code.replace_range(3..4, "!");
+        assert_eq!(TicTacToe::from_code(&code), Err(ParseError::InvalidCharacter(3)));
+
+        // O has moved more often than X
+        let mut board = TicTacToe::from_game_record("40").unwrap();
+        board.board_x = 0;
+        board.board_o = 1;
+        assert_eq!(TicTacToe::from_code(&board.to_code()), Err(ParseError::ImpossiblePosition));
+
+        // X is said to have won a sub-board without three in a row on it
+        let mut board = TicTacToe::from_game_record("40 36").unwrap();
+        board.winners_x = 1 << 2;
+        assert_eq!(TicTacToe::from_code(&board.to_code()), Err(ParseError::ImpossiblePosition));
+
+        // It's the wrong player's turn. With as many X's as O's either player could be next, depending on who started,
+        // but with one more X it has to be O's turn.
+        let mut board = TicTacToe::from_game_record("40").unwrap();
+        board.current_player = Player::X;
+        assert_eq!(TicTacToe::from_code(&board.to_code()), Err(ParseError::ImpossiblePosition));
+    }
+
+    #[test]
+    fn o_can_move_first() {
+        let o_first = Rules { starter: Player::O, ..Rules::default() };
+        let mut board = TicTacToe::with_rules(o_first);
+        assert_eq!(board.current_player(), Player::O);
+        assert_eq!(board.available_actions().len(), 81);
+
+        board.do_action_mut(&40);
+        assert_eq!((board.board_x, board.board_o), (0, 1 << 40));
+        assert_eq!(board.current_player(), Player::X);
+        assert_eq!(board.active_board(), Some(4));
+        assert_eq!(board.available_actions(), &[36, 37, 38, 39, 41, 42, 43, 44]);
+
+        board.do_action_mut(&36);
+        assert_eq!(board.board_x, 1 << 36);
+        assert_eq!((board.current_player(), board.active_board()), (Player::O, Some(0)));
+
+        // The first player is kept when going back and when starting again, and worked out when loading a code
+        let loaded = TicTacToe::from_code(&board.to_code()).unwrap();
+        assert_eq!(loaded, board);
+        assert_eq!(loaded.starter(), Player::O);
+        assert!(board.undo());
+        assert_eq!(board.current_player(), Player::X);
+        assert_eq!(TicTacToe::from_code(&board.to_code()).unwrap().starter(), Player::O);
+        assert_eq!(board.restart(), TicTacToe::with_rules(o_first));
+        let replayed = GameHistory::of(&board).unwrap().replay().unwrap();
+        assert_eq!((replayed.starter(), &replayed), (Player::O, &board));
+
+        // Records and lists of moves are replayed with the rules they were played with
+        assert_eq!(TicTacToe::from_moves(&[40], o_first), Ok(replayed.clone()));
+        assert_eq!(TicTacToe::from_game_record_with_rules("40", o_first).map(|board| board.starter()), Ok(Player::O));
+        assert_eq!(TicTacToe::from_moves(&[40], Rules::default()).unwrap().board_x, 1 << 40);
+    }
+
+    #[test]
+    fn undo_replays_up_to_the_previous_move() {
+        assert!(!TicTacToe::new().undo());
+
+        let mut board = TicTacToe::from_game_record("36 4 37 13 38").unwrap();
+        assert!(board.undo());
+        assert_eq!(board, TicTacToe::from_game_record("36 4 37 13").unwrap());
+        assert_eq!(board.subboard_status(4), SubStatus::InProgress);
+
+        let mut loaded = TicTacToe::from_code(&board.to_code()).unwrap();
+        assert!(!loaded.undo());
+        assert_eq!(loaded, board);
+    }
+
+    #[test]
+    fn last_action_follows_moves() {
+        let mut board = TicTacToe::new();
+        assert_eq!(board.last_action(), None);
+        board.do_action_mut(&40);
+        assert_eq!(board.last_action(), Some(40));
+        board.do_action_mut(&36);
+        assert_eq!(board.last_action(), Some(36));
+        board.undo();
+        assert_eq!(board.last_action(), Some(40));
+    }
+
+    #[test]
+    fn mask_endpoints_are_the_ends_of_each_line() {
+        let endpoints = WIN_MASKS.iter().map(|&mask| mask_endpoints(mask)).collect::<Vec<(u8, u8)>>();
+        assert_eq!(endpoints, vec![(6, 8), (3, 5), (0, 2), (2, 8), (1, 7), (0, 6), (0, 8), (2, 6)]);
+    }
+
+    #[test]
+    fn clicks_map_to_cells_at_any_pixel_ratio() {
+        let size = 450.0;
+        let mut board = TicTacToe::new();
+        for &pixel_ratio in [1.0, 1.5, 2.0, 3.0].iter() {
+            let [a, b, c, d, e, f] = css_to_canvas_transform(pixel_ratio);
+            let to_canvas = |(x, y): (f64, f64)| (a * x + c * y + e, b * x + d * y + f);
+            // Clicks arrive in CSS pixels, so map canvas pixels back through the inverse transform
+            let det = a * d - b * c;
+            let to_css = |(x, y): (f64, f64)| ((d * (x - e) - c * (y - f)) / det, (a * (y - f) - b * (x - e)) / det);
+
+            // The board fills a canvas that's `pixel_ratio` times its CSS size
+            assert_eq!(to_canvas((size, size)), (size * pixel_ratio, size * pixel_ratio));
+
+            for action in 0..81 {
+                // The cell's rectangle as drawn on the canvas
+                let (cell_x, cell_y) = cell_origin(action, size);
+                let (left, top) = to_canvas((cell_x, cell_y));
+                let (right, bottom) = to_canvas((cell_x + size / 9.0, cell_y + size / 9.0));
+                assert!((right - left - size / 9.0 * pixel_ratio).abs() < 1e-9);
+
+                let (click_x, click_y) = to_css(((left + right) / 2.0, (top + bottom) / 2.0));
+                assert_eq!(board.action_for_click(click_x, click_y, size), Some(action));
+            }
+        }
+    }
+
+    #[test]
+    fn generated_win_masks_match_the_table() {
+        let mut generated = win_masks(3, 3);
+        generated.sort_unstable();
+        let mut table = WIN_MASKS.iter().map(|&mask| mask as u64).collect::<Vec<u64>>();
+        table.sort_unstable();
+        assert_eq!(generated, table);
+
+        // Four rows and four columns with two lines each, plus two lines in each of the four directions along diagonals
+        assert_eq!(win_masks(4, 3).len(), 24);
+        assert_eq!(win_masks(3, 4).len(), 0);
+    }
+
+    #[test]
+    fn puct_explores_central_cells_first() {
+        use crate::ai::{ ActionTree, SelectionPolicy };
+
+        let mut tree = ActionTree::new(TicTacToe::new());
+        tree.set_policy(SelectionPolicy::Puct);
+
+        // Record the order in which the root's actions are first simulated
+        let mut explored = Vec::new();
+        while explored.len() < 9 {
+            tree.do_search_step(10);
+            for (&action, node_id) in tree.ranked_actions() {
+                if tree.get_node_visits(node_id) > 0 && !explored.contains(&action) {
+                    explored.push(action);
+                }
+            }
+        }
+
+        assert!(explored.iter().all(|&action| action % 9 == 4), "explored {:?}", explored);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trips_played_positions() {
+        for record in ["", "40", "36 4 37 13 38", "36 4 37 13 38 20 23"].iter() {
+            let board = TicTacToe::from_game_record(record).unwrap();
+            let json = serde_json::to_string(&board).unwrap();
+            let loaded: TicTacToe = serde_json::from_str(&json).unwrap();
+            assert_eq!(loaded, board);
+            assert_eq!(loaded.available_actions(), board.available_actions());
+        }
+
+        // Two more O's than X's
+        let mut impossible = TicTacToe::new();
+        impossible.board_o = 0b11;
+        let json = serde_json::to_string(&impossible).unwrap();
+        assert!(serde_json::from_str::<TicTacToe>(&json).is_err());
+    }
+
+    #[test]
+    fn from_moves_checks_every_move() {
+        let board = TicTacToe::from_moves(&[36, 4, 37, 13, 38], Rules::default()).unwrap();
+        assert_eq!(board, TicTacToe::from_game_record("36 4 37 13 38").unwrap());
+
+        // O has to play in sub-board 0 after X's move, not sub-board 4
+        assert_eq!(TicTacToe::from_moves(&[36, 4, 37, 40], Rules::default()), Err(InvalidMove(3)));
+        // Cell 36 is already taken when O is sent back to sub-board 4
+        assert_eq!(TicTacToe::from_moves(&[40, 36, 4, 36], Rules::default()), Err(InvalidMove(3)));
+        assert_eq!(TicTacToe::from_moves(&[81], Rules::default()), Err(InvalidMove(0)));
+    }
+}
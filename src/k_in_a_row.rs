@@ -0,0 +1,156 @@
+use std::sync::Arc;
+use crate::ai::Game;
+use crate::game::{ Player, win_masks };
+
+const PLAYERS: [Player; 2] = [Player::X, Player::O];
+
+/// A single game of tic-tac-toe on a square board of any size up to 8 by 8, where the first player to get `k` marks in
+/// a row wins. This uses the same search as the ultimate game, so it's handy for trying the AI on simpler games.
+#[derive(Clone, Debug)]
+pub struct KInARow {
+    size: u8,
+    // Every line that wins the game, shared between all states of the same game
+    masks: Arc<[u64]>,
+    board_x: u64,
+    board_o: u64,
+    available_actions: Vec<u8>,
+    current_player: Player,
+    game_over: bool,
+    // The player who won the game, which stays None if the game ended in a draw
+    winner: Option<Player>
+}
+
+impl KInARow {
+    /// Creates an empty `size` by `size` board where `k` in a row wins.
+    pub fn new(size: u8, k: u8) -> Result<Self, String> {
+        if size == 0 || size > 8 {
+            return Err(format!("board size {} must be between 1 and 8", size));
+        }
+        if k == 0 || k > size {
+            return Err(format!("line length {} must be between 1 and the board size", k));
+        }
+
+        Ok(KInARow {
+            size,
+            masks: win_masks(size as usize, k as usize).into(),
+            board_x: 0,
+            board_o: 0,
+            available_actions: (0..size * size).collect(),
+            current_player: Player::X,
+            game_over: false,
+            winner: None
+        })
+    }
+
+    /// Creates the classic game of tic-tac-toe on a single 3 by 3 board. It's small enough to be solved, which makes it
+    /// useful for checking that the search plays perfectly.
+    pub fn regular() -> Self {
+        KInARow::new(3, 3).expect("3 in a row fits on a 3 by 3 board")
+    }
+
+    /// Returns the length of each side of the board.
+    pub fn size(&self) -> u8 {
+        self.size
+    }
+}
+
+impl Game for KInARow {
+    type Action = u8;
+    type Player = Player;
+
+    fn available_actions(&self) -> &[Self::Action] {
+        &self.available_actions
+    }
+
+    fn do_action_mut(&mut self, action: &Self::Action) {
+        let cell_mask = 1u64 << action;
+        let player_board = match self.current_player {
+            Player::X => {
+                self.board_x |= cell_mask;
+                self.board_x
+            },
+            Player::O => {
+                self.board_o |= cell_mask;
+                self.board_o
+            }
+        };
+        self.available_actions.retain(|a| a != action);
+
+        // Only lines through the new mark can have been completed by it
+        let won = self.masks.iter().any(|&mask| mask & cell_mask != 0 && mask & !player_board == 0);
+        if won {
+            self.winner = Some(self.current_player);
+            self.game_over = true;
+            self.available_actions.clear();
+            return;
+        }
+
+        self.current_player = match self.current_player {
+            Player::X => Player::O,
+            Player::O => Player::X
+        };
+        self.game_over = self.available_actions.is_empty();
+    }
+
+    fn get_players(&self) -> &[Self::Player] {
+        &PLAYERS
+    }
+
+    fn current_player(&self) -> Self::Player {
+        self.current_player
+    }
+
+    fn winner(&self) -> Option<Self::Player> {
+        self.winner
+    }
+
+    fn game_over(&self) -> bool {
+        self.game_over
+    }
+
+    fn is_initial_state(&self) -> bool {
+        self.board_x | self.board_o == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::ActionTree;
+
+    #[test]
+    fn new_rejects_bad_dimensions() {
+        assert!(KInARow::new(0, 1).is_err());
+        assert!(KInARow::new(9, 3).is_err());
+        assert!(KInARow::new(3, 4).is_err());
+        assert_eq!(KInARow::new(4, 3).unwrap().available_actions().len(), 16);
+    }
+
+    #[test]
+    fn search_completes_a_line() {
+        // X has two cells of the top row and is about to move
+        let mut board = KInARow::new(3, 3).unwrap();
+        for action in [0, 3, 1, 4].iter() {
+            board.do_action_mut(action);
+        }
+
+        let mut tree = ActionTree::new(board);
+        for _ in 0..500 {
+            tree.do_search_step(10);
+        }
+        assert_eq!(tree.get_best_action().map(|(action, _)| *action), Some(2));
+
+        tree.do_action(&2).unwrap();
+        assert_eq!(tree.root_state().winner(), Some(Player::X));
+    }
+
+    #[test]
+    fn full_board_without_a_line_is_a_draw() {
+        let mut board = KInARow::new(3, 3).unwrap();
+        for action in [0, 1, 2, 4, 3, 5, 7, 6, 8].iter() {
+            board.do_action_mut(action);
+        }
+        assert!(board.game_over());
+        assert_eq!(board.winner(), None);
+    }
+}
@@ -1,11 +1,24 @@
-mod game;
-mod ai;
+pub mod game;
+pub mod ai;
+pub mod k_in_a_row;
+pub mod connect_four;
+mod timer;
 
-use ai::{ Game, ActionTree };
-use game::{ Player, TicTacToe };
+use ai::{ Game, ActionTree, BackupPolicy, FinalMoveSelection, IllegalAction, Scoring, SelectionPolicy };
+#[cfg(feature = "serde")]
+use ai::OpeningBook;
+#[cfg(feature = "serde")]
+use game::GameHistory;
+use connect_four::ConnectFour;
+use k_in_a_row::KInARow;
+use game::{
+    DrawOptions, GameResult, GreedyPolicy, Player, Rules, SendToFullBoard, SubStatus, RecordError,
+    Theme, TicTacToe
+};
 
+use std::time::Duration;
 use wasm_bindgen::prelude::*;
-use web_sys::CanvasRenderingContext2d;
+use web_sys::{ CanvasRenderingContext2d, HtmlCanvasElement };
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
 // allocator.
@@ -25,42 +38,371 @@ pub fn set_panic_hook() {
     console_error_panic_hook::set_once();
 }
 
+/// Checks that a size passed in from JavaScript is a positive, finite number.
+fn check_size(size: f64) -> Result<f64, String> {
+    if size.is_finite() && size > 0.0 {
+        Ok(size)
+    } else {
+        Err(format!("invalid size: {}", size))
+    }
+}
+
+/// Checks that a coordinate passed in from JavaScript is a finite number.
+fn check_coordinate(coordinate: f64) -> Result<f64, String> {
+    if coordinate.is_finite() {
+        Ok(coordinate)
+    } else {
+        Err(format!("invalid coordinate: {}", coordinate))
+    }
+}
+
+/// Plays an action passed in from JavaScript, failing without changing anything if it isn't legal.
+fn play_checked<G: Game>(state: &mut G, action: G::Action) -> Result<(), String> {
+    if !state.available_actions().contains(&action) {
+        return Err(IllegalAction.to_string());
+    }
+    state.do_action_mut(&action);
+    Ok(())
+}
+
+fn player_name(player: Player) -> String {
+    match player {
+        Player::X => "X".to_owned(),
+        Player::O => "O".to_owned()
+    }
+}
+
+// Reads a player named by `player_name`
+fn parse_player(name: &str) -> Result<Player, JsValue> {
+    match name {
+        "X" => Ok(Player::X),
+        "O" => Ok(Player::O),
+        _ => Err(JsValue::from_str(&format!("invalid player: {}", name)))
+    }
+}
+
+// Puts together the rules of a game from JavaScript, where `starter` is the player who moves first, "X" or "O"
+fn parse_rules(send_rule: SendToFullBoard, subboard_tiebreak: bool, starter: &str) -> Result<Rules, JsValue> {
+    Ok(Rules { send_rule, subboard_tiebreak, starter: parse_player(starter)? })
+}
+
+/// Names the result of a finished game for JavaScript: "X" or "O" for the winner, or "draw".
+fn result_name(result: GameResult) -> String {
+    match result {
+        GameResult::Win(player) => player_name(player),
+        GameResult::Draw => "draw".to_owned()
+    }
+}
+
+/// Encodes an optional player as a number for JavaScript: 0 for nobody, 1 for X and 2 for O.
+fn player_code(player: Option<Player>) -> i8 {
+    match player {
+        None => 0,
+        Some(Player::X) => 1,
+        Some(Player::O) => 2
+    }
+}
+
 /// A newtype wrapper for TicTacToe to do handle `wasm_bindgen`'s inability to make bindings for generic impls.
+/// Also holds the theme used to draw the board.
 #[wasm_bindgen]
-pub struct Board(TicTacToe);
+pub struct Board(TicTacToe, Theme);
 
 #[wasm_bindgen]
 impl Board {
     #[wasm_bindgen(constructor)]
     pub fn new() -> Self {
-        Board(TicTacToe::new())
+        Board(TicTacToe::new(), Theme::default())
+    }
+
+    /// Creates an empty board for a game played with the given rules, where `starter` is the player who moves first,
+    /// "X" or "O".
+    pub fn with_rules(send_rule: SendToFullBoard, subboard_tiebreak: bool, starter: &str) -> Result<Board, JsValue> {
+        Ok(Board(TicTacToe::with_rules(parse_rules(send_rule, subboard_tiebreak, starter)?), Theme::default()))
+    }
+
+    /// Draws the board. `size` is in CSS pixels, and the drawing is scaled by `device_pixel_ratio` so that it's sharp
+    /// on a canvas whose width and height are that many times its CSS size. Pass 1 for a canvas sized in CSS pixels.
+    pub fn draw(&self, ctx: &CanvasRenderingContext2d, size: f64, device_pixel_ratio: f64) -> Result<(), JsValue> {
+        self.0.draw(ctx, check_size(size)?, &self.1, check_size(device_pixel_ratio)?)
+    }
+
+    /// Draws the board, with the last move highlighted, onto a new canvas `size` CSS pixels square with a white
+    /// background, and returns it as a PNG data URL for sharing or thumbnails. This needs a page with a DOM to create the
+    /// canvas in, so it fails in workers and outside the browser. To check it by hand, run
+    /// `open(board.to_png_data_url(300))` from the browser console on the game page.
+    pub fn to_png_data_url(&self, size: f64) -> Result<String, JsValue> {
+        let size = check_size(size)?;
+        let document = web_sys::window()
+            .and_then(|window| window.document())
+            .ok_or_else(|| JsValue::from_str("there is no document to create a canvas in"))?;
+        let canvas = document.create_element("canvas")?.dyn_into::<HtmlCanvasElement>()?;
+        canvas.set_width(size.ceil() as u32);
+        canvas.set_height(size.ceil() as u32);
+        let ctx = canvas.get_context("2d")?
+            .ok_or_else(|| JsValue::from_str("the canvas has no 2D context"))?
+            .dyn_into::<CanvasRenderingContext2d>()?;
+
+        ctx.set_fill_style_str("#fff");
+        ctx.fill_rect(0.0, 0.0, size, size);
+        self.0.draw_with_last_move(&ctx, size, &self.1, 1.0)?;
+        canvas.to_data_url()
     }
 
-    pub fn draw(&self, ctx: &CanvasRenderingContext2d, size: f64) -> Result<(), JsValue> {
-        self.0.draw(ctx, size)
+    /// Changes the colors the board is drawn with. Each color is a CSS color string, e.g. "#f00" or "red".
+    pub fn set_theme(&mut self, grid: &str, x: &str, o: &str, x_highlight: &str, o_highlight: &str, last_move: &str) {
+        self.1 = Theme {
+            grid: grid.to_owned(),
+            x: x.to_owned(),
+            o: o.to_owned(),
+            x_highlight: x_highlight.to_owned(),
+            o_highlight: o_highlight.to_owned(),
+            last_move: last_move.to_owned()
+        };
     }
 
-    pub fn action_for_click(&mut self, x: f64, y: f64, board_size: f64) -> Option<u8> {
-        self.0.action_for_click(x, y, board_size)
+    /// Draws the board with the cell of the last move highlighted.
+    pub fn draw_with_last_move(
+        &self, ctx: &CanvasRenderingContext2d, size: f64, device_pixel_ratio: f64
+    ) -> Result<(), JsValue> {
+        self.0.draw_with_last_move(ctx, check_size(size)?, &self.1, check_size(device_pixel_ratio)?)
     }
 
-    pub fn do_action_mut(&mut self, action: u8) {
-        self.0.do_action_mut(&action);
+    /// Draws the board like `draw_with_last_move`, with the mark of the last move drawn `last_move_progress` (from 0 to 1)
+    /// of the way, for animating it by drawing a frame at a time.
+    pub fn draw_animated(
+        &self, ctx: &CanvasRenderingContext2d, size: f64, device_pixel_ratio: f64, last_move_progress: f64
+    ) -> Result<(), JsValue> {
+        self.0.draw_animated(ctx, check_size(size)?, &self.1, check_size(device_pixel_ratio)?, last_move_progress)
+    }
+
+    /// Draws the board with any combination of the extras in `options`: highlighting the last move, labelling each
+    /// cell with its coordinates (e.g. "E5" for the center), and drawing the mark of the last move partway.
+    pub fn draw_with_options(
+        &self, ctx: &CanvasRenderingContext2d, size: f64, device_pixel_ratio: f64, options: &DrawOptions
+    ) -> Result<(), JsValue> {
+        self.0.draw_with_options(ctx, check_size(size)?, &self.1, check_size(device_pixel_ratio)?, options)
+    }
+
+    /// Finds the legal action at a click. The coordinates and board size are in CSS pixels, even when the board is
+    /// drawn with a device pixel ratio.
+    pub fn action_for_click(&mut self, x: f64, y: f64, board_size: f64) -> Result<Option<u8>, JsValue> {
+        Ok(self.0.action_for_click(check_coordinate(x)?, check_coordinate(y)?, check_size(board_size)?))
+    }
+
+    /// Plays an action, failing without changing anything if it isn't legal (see `is_legal`).
+    pub fn do_action_mut(&mut self, action: u8) -> Result<(), JsValue> {
+        Ok(play_checked(&mut self.0, action)?)
+    }
+
+    /// Returns the index of the sub-board the current player has to play in, or None if they can play in any open
+    /// sub-board.
+    pub fn active_board(&self) -> Option<u8> {
+        self.0.active_board()
+    }
+
+    /// Returns the actions the current player can take, in increasing order. This is empty once the game is over.
+    pub fn legal_moves(&self) -> Vec<u8> {
+        self.0.available_actions().to_vec()
+    }
+
+    /// Checks whether the current player can take the given action.
+    pub fn is_legal(&self, action: u8) -> bool {
+        self.0.available_actions().contains(&action)
     }
 
     pub fn current_player(&self) -> String {
-        match self.0.current_player() {
-            Player::X => "X".to_owned(),
-            Player::O => "O".to_owned()
-        }
+        player_name(self.0.current_player())
     }
 
     pub fn is_game_over(&self) -> bool {
         self.0.game_over()
     }
 
+    /// Returns "X" or "O" once that player has won the game, or None while the game is still going or if it was drawn.
+    pub fn winner(&self) -> Option<String> {
+        self.0.winner().map(player_name)
+    }
+
+    /// Returns whether the game is over without either player winning.
+    pub fn is_draw(&self) -> bool {
+        self.0.result() == Some(GameResult::Draw)
+    }
+
+    /// Returns how the game ended: "X" or "O" if that player won, or "draw". Returns None while the game is still going.
+    pub fn result(&self) -> Option<String> {
+        self.0.result().map(result_name)
+    }
+
+    /// Returns the indices of the sub-boards that are full without either player winning them.
+    pub fn drawn_subboards(&self) -> Vec<u8> {
+        self.0.drawn_subboards()
+    }
+
+    /// Returns the number of sub-boards won by X and by O, in that order, for showing the score.
+    pub fn subboards_won(&self) -> Vec<u32> {
+        let (x_boards, o_boards) = self.0.subboards_won();
+        vec![x_boards, o_boards]
+    }
+
+    /// Returns who has played in each of the 81 cells, indexed by action: 0 for empty, 1 for X and 2 for O.
+    pub fn cells(&self) -> Vec<i8> {
+        (0..81).map(|action| player_code(self.0.cell_owner(action))).collect()
+    }
+
+    /// Describes the position in words for screen readers, including whose turn it is, where they can play, and who
+    /// has won or tied each sub-board.
+    pub fn describe(&self) -> String {
+        self.0.describe()
+    }
+
+    /// Returns who has won each of the 9 sub-boards: 0 for nobody, 1 for X and 2 for O.
+    pub fn subboard_winners(&self) -> Vec<i8> {
+        (0..9)
+            .map(|board_i| match self.0.subboard_status(board_i) {
+                SubStatus::Won(player) => player_code(Some(player)),
+                _ => 0
+            })
+            .collect()
+    }
+
+    /// Plays the rest of the game with a deterministic policy and returns the winner's name, if there was one.
+    pub fn play_out_greedy(&mut self, policy: GreedyPolicy) -> Option<String> {
+        self.0.play_out_greedy(policy).map(player_name)
+    }
+
+    /// Replays a game record and returns every position in it, including the starting position, so that a UI can show
+    /// the whole game without replaying it for every step.
+    pub fn replay_all(record: &str) -> Result<Vec<String>, JsValue> {
+        replay_positions(record).map_err(|err| JsValue::from_str(&err.to_string()))
+    }
+
+    /// Replaces the board with the position reached by playing a list of actions from an empty board with the same
+    /// rules as this one, failing if any of them is illegal.
+    pub fn load_moves(&mut self, actions: Vec<u8>) -> Result<(), JsValue> {
+        self.0 = TicTacToe::from_moves(&actions, self.0.rules()).map_err(|err| JsValue::from_str(&err.to_string()))?;
+        Ok(())
+    }
+
+    /// Saves the moves played so far as JSON (see `GameHistory`), noting who played X and O, such as "human" or "ai", if
+    /// given. Fails for positions loaded with `from_code`, since their moves aren't known.
+    #[cfg(feature = "serde")]
+    pub fn export_history(&self, x_player: Option<String>, o_player: Option<String>) -> Result<String, JsValue> {
+        let mut history = GameHistory::of(&self.0)
+            .ok_or_else(|| JsValue::from_str("the moves of this position aren't known"))?;
+        history.x_player = x_player;
+        history.o_player = o_player;
+        Ok(history.to_json())
+    }
+
+    /// Replaces the board with the game saved by `export_history`, replaying its moves to check that each one is legal.
+    /// The board is left as it was if the history can't be loaded.
+    #[cfg(feature = "serde")]
+    pub fn import_history(&mut self, json: &str) -> Result<(), JsValue> {
+        let history = GameHistory::from_json(json).map_err(|err| JsValue::from_str(&err))?;
+        self.0 = history.replay().map_err(|err| JsValue::from_str(&err.to_string()))?;
+        Ok(())
+    }
+
+    /// Returns a short code for the current position that can be shared, e.g. in a link, and loaded with `from_code`.
+    pub fn to_code(&self) -> String {
+        self.0.to_code()
+    }
+
+    /// Loads a position from a code made by `to_code`.
+    pub fn from_code(code: &str) -> Result<Board, JsValue> {
+        TicTacToe::from_code(code)
+            .map(|board| Board(board, Theme::default()))
+            .map_err(|err| JsValue::from_str(&err.to_string()))
+    }
+
+    /// Counts the ways the game can be played to the end from the current position, or returns None if there are too
+    /// many empty cells left to count them.
+    pub fn count_leaves(&self) -> Option<f64> {
+        self.0.count_leaves().map(|leaves| leaves as f64)
+    }
+
+    /// Takes back the last move. Returns false if there is no move to undo.
+    pub fn undo(&mut self) -> bool {
+        self.0.undo()
+    }
+
+    /// Sets whether a game that fills up without a winner goes to the player who won more sub-boards. This should be
+    /// set before the game starts.
+    pub fn set_subboard_tiebreak(&mut self, enabled: bool) {
+        self.0.set_subboard_tiebreak(enabled);
+    }
+
     pub fn reset(&mut self) {
-        self.0 = TicTacToe::new();
+        self.0 = self.0.restart();
+    }
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Board::new()
+    }
+}
+
+/// Runs a fresh search of `sims` single-simulation steps on the position reached by a game record and returns the
+/// estimated win probability for the player to move, or NaN if the record can't be replayed.
+fn evaluate_position(record: &str, sims: u32) -> f64 {
+    let state = match TicTacToe::from_game_record(record) {
+        Ok(state) => state,
+        Err(_) => return f64::NAN
+    };
+
+    let mut tree = ActionTree::new(state);
+    for _ in 0..sims {
+        tree.do_search_step(1);
+    }
+    tree.root_win_probability().unwrap_or(0.5)
+}
+
+/// Evaluates several positions, each given as a game record (see `TicTacToe::from_game_record`), returning the win
+/// probability for the player to move in each one. Positions whose records can't be replayed evaluate to NaN.
+pub fn evaluate_positions(positions: &[String], sims: u32) -> Vec<f64> {
+    positions.iter()
+        .map(|record| evaluate_position(record, sims))
+        .collect()
+}
+
+/// JavaScript version of `evaluate_positions`, taking an array of game record strings. Non-string entries evaluate to
+/// NaN.
+#[wasm_bindgen(js_name = evaluate_positions)]
+pub fn evaluate_positions_js(positions: &js_sys::Array, sims: u32) -> Vec<f64> {
+    positions.iter()
+        .map(|record| record.as_string().map_or(f64::NAN, |record| evaluate_position(&record, sims)))
+        .collect()
+}
+
+/// Replays a game record and returns the number of legal moves in the starting position and after each move.
+pub fn branching_over_record(record: &str) -> Result<Vec<u32>, RecordError> {
+    let mut branching = Vec::new();
+    TicTacToe::replay_game_record(record, Rules::default(), |board| branching.push(board.active_branching()))?;
+    Ok(branching)
+}
+
+/// Replays a game record and returns the starting position and the position after each move, formatted as strings.
+fn replay_positions(record: &str) -> Result<Vec<String>, RecordError> {
+    let mut positions = Vec::new();
+    TicTacToe::replay_game_record(record, Rules::default(), |board| positions.push(board.to_string()))?;
+    Ok(positions)
+}
+
+/// JavaScript version of `branching_over_record`.
+#[wasm_bindgen]
+pub fn position_branching_over_record(record: &str) -> Result<Vec<u32>, JsValue> {
+    branching_over_record(record).map_err(|err| JsValue::from_str(&err.to_string()))
+}
+
+/// Checks whether two game records lead to the same position, even if their moves were played in different orders.
+/// Returns false if either record can't be replayed.
+#[wasm_bindgen]
+pub fn positions_equal(record_a: &str, record_b: &str) -> bool {
+    match (TicTacToe::from_game_record(record_a), TicTacToe::from_game_record(record_b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false
     }
 }
 
@@ -72,6 +414,64 @@ pub struct ActionStats {
     pub wins: u32
 }
 
+/// Measures how the AI's search is shaped, to be sent to Javascript for logging while tuning the search. See
+/// `ActionTree::effective_branching` and `ActionTree::simulations_per_node`.
+#[wasm_bindgen]
+pub struct SearchMetrics {
+    pub node_count: u32,
+    pub root_visits: u32,
+    pub max_depth: u32,
+    pub effective_branching: f64,
+    pub simulations_per_node: f64
+}
+
+/// Holds statistics about the AI's second choice to be sent to Javascript for UTTTMonteCarloAI::runner_up. `gap` is how
+/// much lower its winrate is than the best action's.
+#[wasm_bindgen]
+pub struct RunnerUp {
+    pub action: u8,
+    pub sims: u32,
+    pub wins: u32,
+    pub gap: f64
+}
+
+// Converts a time budget in milliseconds from JavaScript. Negative or NaN budgets mean no time at all, and budgets too
+// long for a `Duration` are unlimited
+fn budget_from_millis(millis: f64) -> Duration {
+    Duration::try_from_secs_f64(millis.max(0.0) / 1000.0).unwrap_or(Duration::MAX)
+}
+
+// The search behind `search_for_ms` on every AI wrapper, since `#[wasm_bindgen]` can't export generic methods
+fn search_for_ms<G: Game>(tree: &mut ActionTree<G>, millis: f64, sims_per_step: u32) -> u32 {
+    tree.search_for(budget_from_millis(millis), sims_per_step)
+}
+
+// Reseeds the simulations of an AI wrapper's tree for a new game. On the web, they're seeded randomly so that each game
+// is played differently, while elsewhere they carry on from where the last game left off.
+fn reseed_randomly<G: Game>(tree: &mut ActionTree<G>) {
+    if cfg!(target_arch = "wasm32") {
+        tree.reseed((js_sys::Math::random() * u64::MAX as f64) as u64);
+    }
+}
+
+// Gets the statistics of the child `node_id`, which is reached by playing `action`
+fn action_stats<G: Game<Action = u8>>(tree: &ActionTree<G>, action: u8, node_id: usize) -> ActionStats {
+    ActionStats {
+        action,
+        sims: tree.get_node_total_points(node_id),
+        wins: tree.get_node_earned_points(node_id)
+    }
+}
+
+// Gets statistics for every move at the root, from the most to the least simulated
+fn all_action_stats<G: Game<Action = u8>>(tree: &ActionTree<G>) -> Vec<ActionStats> {
+    let mut actions = tree.ranked_actions();
+    actions.sort_by_key(|(_, node_id)| std::cmp::Reverse(tree.get_node_visits(*node_id)));
+    actions.into_iter()
+        .map(|(action, node_id)| action_stats(tree, *action, node_id))
+        .collect()
+}
+
 /// A newtype wrapper for `ActionTree<TicTacToe>` that allows JavaScript to control an ActionTree specifically for
 /// Ultimate TicTacToe. This is necessary because `#[wasm_bindgen]` doesn't work on generic impls.
 #[wasm_bindgen]
@@ -81,38 +481,1123 @@ pub struct UTTTMonteCarloAI(ActionTree<TicTacToe>);
 impl UTTTMonteCarloAI {
     #[wasm_bindgen(constructor)]
     pub fn new() -> Self {
-        UTTTMonteCarloAI(ActionTree::new(TicTacToe::new()))
+        UTTTMonteCarloAI(ActionTree::with_opening_book(TicTacToe::new(), TicTacToe::opening_book()))
+    }
+
+    /// Creates an AI whose simulations are seeded with the given number, such as `Date.now()`, so that it doesn't play
+    /// the same way every game. Any number can be used, and different numbers give different seeds.
+    pub fn with_seed(seed: f64) -> Self {
+        let mut tree = ActionTree::with_seed(TicTacToe::new(), seed.to_bits());
+        tree.set_opening_book(TicTacToe::opening_book());
+        UTTTMonteCarloAI(tree)
+    }
+
+    /// Creates a seeded AI (see `with_seed`) for games played with the given rules, like `Board::with_rules`. Letting O
+    /// move first means a human can choose to go second.
+    pub fn with_rules(
+        send_rule: SendToFullBoard, subboard_tiebreak: bool, starter: &str, seed: f64
+    ) -> Result<UTTTMonteCarloAI, JsValue> {
+        let state = TicTacToe::with_rules(parse_rules(send_rule, subboard_tiebreak, starter)?);
+        let mut tree = ActionTree::with_seed(state, seed.to_bits());
+        tree.set_opening_book(TicTacToe::opening_book());
+        Ok(UTTTMonteCarloAI(tree))
+    }
+
+    /// Gets the move the opening book has for the current position, if there is one, so that it can be played
+    /// straight away instead of searching.
+    pub fn consult_book(&self) -> Option<u8> {
+        self.0.consult_book()
+    }
+
+    /// Replaces the built-in opening book with one loaded from a JSON object mapping position codes (see
+    /// `Board::to_code`) to moves.
+    #[cfg(feature = "serde")]
+    pub fn load_opening_book(&mut self, json: &str) -> Result<(), JsValue> {
+        let book = OpeningBook::from_json(json).map_err(|err| JsValue::from_str(&err))?;
+        self.0.set_opening_book(book);
+        Ok(())
     }
 
     pub fn do_search_step(&mut self, num_sims: u32) {
         self.0.do_search_step(num_sims);
     }
 
+    /// Runs `iterations` search steps of `sims_each` simulations each. See `ActionTree::do_search_iterations`.
+    pub fn do_search_iterations(&mut self, iterations: u32, sims_each: u32) {
+        self.0.do_search_iterations(iterations, sims_each);
+    }
+
+    /// Picks a move at random, favouring the moves that were searched the most. Lower temperatures `tau` play more like
+    /// `get_best_action`, and higher ones play more randomly, which makes for easier opponents.
+    pub fn get_move_temperature(&mut self, tau: f64) -> Option<u8> {
+        self.0.sample_action(tau)
+    }
+
+    /// Picks a move like `get_best_action`, except that on the first move of the game one of the `top_k` most searched
+    /// moves is picked at random, so that games against the AI open differently.
+    pub fn randomize_first_move(&mut self, top_k: usize) -> Option<u8> {
+        self.0.randomize_first_move(top_k)
+    }
+
+    /// Runs `total_sims` single-simulation search steps, calling `callback` with the number of simulations run so far
+    /// and the current best move (or undefined) after every `every` simulations and at the end. This lets the page show
+    /// progress and preview the best move during a long search. Returns the first error thrown by the callback, once
+    /// the search is done.
+    pub fn search_with_callback(
+        &mut self, total_sims: u32, every: u32, callback: &js_sys::Function
+    ) -> Result<(), JsValue> {
+        let mut result = Ok(());
+        self.0.search_with_callback(total_sims, every, |sims, action| {
+            let action = action.map_or(JsValue::UNDEFINED, |&action| JsValue::from(action));
+            if let Err(err) = callback.call2(&JsValue::NULL, &JsValue::from(sims), &action) {
+                if result.is_ok() {
+                    result = Err(err);
+                }
+            }
+        });
+        result
+    }
+
+    /// Keeps searching while the human is choosing a move, spreading the simulations between all of their options.
+    pub fn ponder_step(&mut self, num_sims: u32) {
+        self.0.ponder_step(num_sims);
+    }
+
     pub fn get_best_action(&self) -> Option<ActionStats> {
         self.0.get_best_action()
-            .map(|(action, node_id)| ActionStats {
+            .map(|(action, node_id)| action_stats(&self.0, *action, node_id))
+    }
+
+    /// Gets statistics for every move the AI has considered, from the most to the least simulated.
+    pub fn get_all_action_stats(&self) -> Vec<ActionStats> {
+        all_action_stats(&self.0)
+    }
+
+    /// Runs `sims` more simulations and returns the most simulated move for the player to move, with its statistics, so
+    /// that a teaching UI can suggest a move. This searches the AI's own tree, so it only hints at the human's position
+    /// while the AI is following their game, and the simulations are kept for the AI's next search. Use `hint_for` to
+    /// get a hint for any position without touching the AI. Returns None if the game is over.
+    pub fn hint(&mut self, sims: u32) -> Option<ActionStats> {
+        if self.0.is_game_over() {
+            return None;
+        }
+
+        self.0.do_search_sims(sims);
+        match self.0.most_visited_action() {
+            Some((action, node_id)) => Some(action_stats(&self.0, *action, node_id)),
+            None => self.0.root_state().available_actions().first()
+                .map(|&action| ActionStats { action, sims: 0, wins: 0 })
+        }
+    }
+
+    /// Gets a hint (see `hint`) for the player to move on `board`, by searching a new tree that's thrown away afterwards.
+    pub fn hint_for(board: &Board, sims: u32) -> Option<ActionStats> {
+        UTTTMonteCarloAI(ActionTree::new(board.0.clone())).hint(sims)
+    }
+
+    /// Gets the statistics of a single move at the root, or None if it hasn't been considered yet.
+    pub fn child_stats(&self, action: u8) -> Option<ActionStats> {
+        self.0.child_stats(&action).map(|(wins, sims)| ActionStats { action, sims, wins })
+    }
+
+    /// Gets the winrate and the exploration bonus that make up the score of a move at the root, in that order, or an
+    /// empty array if the move hasn't been simulated yet.
+    pub fn score_components(&self, action: u8) -> Vec<f64> {
+        self.0.score_components(&action)
+            .map_or_else(Vec::new, |(exploitation, exploration)| vec![exploitation, exploration])
+    }
+
+    /// Gets the winrate of every cell for the current player, indexed by action, for painting a heatmap over the board.
+    /// Legal moves that haven't been searched yet are 0.5, and cells that can't be played are -1.
+    pub fn evaluation_grid(&self) -> Vec<f32> {
+        let mut grid = vec![-1.0; 81];
+        for &action in self.0.root_state().available_actions() {
+            grid[action as usize] = 0.5;
+        }
+        for (action, node_id) in self.0.ranked_actions() {
+            let total_points = self.0.get_node_total_points(node_id);
+            if total_points > 0 {
+                grid[*action as usize] = self.0.get_node_earned_points(node_id) as f32 / total_points as f32;
+            }
+        }
+        grid
+    }
+
+    /// Runs search steps of `sims_per_step` simulations each for `millis` milliseconds, and returns how many steps were
+    /// run. At least one step is always run.
+    pub fn search_for_ms(&mut self, millis: f64, sims_per_step: u32) -> u32 {
+        search_for_ms(&mut self.0, millis, sims_per_step)
+    }
+
+    /// Runs search steps of `step` simulations each until one move has more than `confidence` (from 0 to 1) of the
+    /// simulations, or until `max_sims` simulations have been run. Returns the number of simulations run.
+    pub fn search_until_confident(&mut self, step: u32, confidence: f64, max_sims: u32) -> u32 {
+        self.0.search_until_confident(step, confidence, max_sims)
+    }
+
+    /// Searches for up to `millis` milliseconds and returns the most simulated action, or any legal action if the search
+    /// didn't get far enough to try them. Only returns None if the game is over.
+    pub fn best_move_within(&mut self, millis: f64, sims_per_step: u32) -> Option<u8> {
+        if self.0.is_game_over() {
+            return None;
+        }
+
+        self.search_for_ms(millis, sims_per_step);
+        self.0.most_visited_action()
+            .map(|(action, _)| *action)
+            .or_else(|| self.0.root_state().available_actions().first().copied())
+    }
+
+    /// Estimates how likely the current player is to win each of the nine sub-boards, based on the simulations run
+    /// since the last action.
+    pub fn subboard_win_probabilities(&self) -> Vec<f64> {
+        let mut probabilities = self.0.region_win_probabilities(&self.0.current_player());
+        probabilities.resize(9, 0.0);
+        probabilities
+    }
+
+    /// Gets a win probability for the current player that starts at 0.5 and firms up as the search progresses. See
+    /// `ActionTree::smoothed_eval`.
+    pub fn smoothed_eval(&self, prior_weight: f64) -> f64 {
+        self.0.smoothed_eval(prior_weight)
+    }
+
+    /// Estimates the chance that the player to move wins, from 0 to 1, for showing an evaluation bar. This is from the
+    /// perspective of `current_player`, not of whichever player the AI is playing. Returns 0.5 before any searching.
+    pub fn win_probability(&self) -> f64 {
+        self.0.root_win_probability().unwrap_or(0.5)
+    }
+
+    /// Checks whether the current player is losing badly enough to resign, with an estimated chance of winning below
+    /// `threshold` (see `ActionTree::should_resign`). Always false until the AI has searched enough to be sure.
+    pub fn should_resign(&self, threshold: f64) -> bool {
+        self.0.should_resign(threshold)
+    }
+
+    /// Checks whether the current player can't expect better than a draw, so the AI could offer one (see
+    /// `ActionTree::should_claim_draw`). Always false until the AI has searched enough to be sure.
+    pub fn should_claim_draw(&self, margin: f64) -> bool {
+        self.0.should_claim_draw(margin)
+    }
+
+    /// Summarizes the search tree as a JSON string, with the number of nodes, simulations from the root, deepest line
+    /// searched and moves considered at the root.
+    pub fn tree_stats_json(&self) -> String {
+        self.0.stats().to_string()
+    }
+
+    /// Measures the size and shape of the search tree, for telling whether the search is too wide or too deep.
+    pub fn search_metrics(&self) -> SearchMetrics {
+        let stats = self.0.stats();
+        SearchMetrics {
+            node_count: stats.node_count as u32,
+            root_visits: stats.root_visits,
+            max_depth: stats.max_depth as u32,
+            effective_branching: self.0.effective_branching(),
+            simulations_per_node: self.0.simulations_per_node()
+        }
+    }
+
+    /// Exports the statistics of every move the AI has considered as CSV.
+    pub fn stats_csv(&self) -> String {
+        self.0.stats_csv()
+    }
+
+    /// Gets the sequence of moves that the AI currently expects to be played.
+    pub fn principal_variation(&self) -> Vec<u8> {
+        self.0.principal_variation()
+    }
+
+    pub fn runner_up(&self) -> Option<RunnerUp> {
+        self.0.runner_up()
+            .map(|(action, node_id, gap)| RunnerUp {
                 action: *action,
                 sims: self.0.get_node_total_points(node_id),
-                wins: self.0.get_node_earned_points(node_id)
+                wins: self.0.get_node_earned_points(node_id),
+                gap
             })
     }
 
-    pub fn do_action(&mut self, action: u8) {
-        self.0.do_action(&action);
+    /// Plays a move, failing without changing anything if it isn't legal.
+    pub fn do_action(&mut self, action: u8) -> Result<(), JsValue> {
+        self.0.do_action(&action).map_err(|err| JsValue::from_str(&err.to_string()))
+    }
+
+    /// Sets the exploration constant used by the search, which defaults to sqrt(2). Higher values make the AI try more
+    /// moves, while lower values make it focus on the moves that look best so far.
+    pub fn set_exploration(&mut self, exploration: f64) {
+        self.0.set_exploration(exploration);
+    }
+
+    /// Sets how the AI picks its move from the search results. The default, `MaxWinrate`, picks the move that has won
+    /// most often, while `MostVisited` is more robust since it ignores moves that only look good because they were
+    /// rarely tried.
+    pub fn set_final_move_selection(&mut self, final_move: FinalMoveSelection) {
+        self.0.set_final_move_selection(final_move);
+    }
+
+    /// Sets how the search chooses which moves to explore. The default, `Uct`, uses the exploration constant, while
+    /// `Ucb1Tuned` adapts to how much the results of each move vary and `Puct` tries central cells first.
+    pub fn set_policy(&mut self, policy: SelectionPolicy) {
+        self.0.set_policy(policy);
+    }
+
+    /// Sets the first-play urgency reduction (see `ActionTree::set_fpu_reduction`), so that the AI stops trying every
+    /// move before focusing on the good ones. Values around 0.2 work well, and `undefined` turns it off again.
+    pub fn set_fpu_reduction(&mut self, reduction: Option<f64>) {
+        self.0.set_fpu_reduction(reduction);
+    }
+
+    /// Stops each simulation after `max_depth` moves and scores it by the sub-boards each player has won, so that the AI
+    /// can run more simulations in the same time. `undefined` plays every simulation to the end again.
+    pub fn set_rollout_depth(&mut self, max_depth: Option<u32>) {
+        self.0.set_rollout_depth(max_depth);
+    }
+
+    /// Sets how the AI combines the results of its simulations. The default, `Average`, uses each move's winrate, while
+    /// `Max` assumes the opponent replies with their best move, which finds forced wins in endgames sooner. This
+    /// restarts the search, since the results so far were combined the other way.
+    pub fn set_backup_policy(&mut self, backup: BackupPolicy) {
+        self.0.set_backup_policy(backup);
+        let state = self.0.root_state().clone();
+        self.0.reset_to(state);
+    }
+
+    /// Adds random noise to how promising the AI thinks each move is, so that it varies its play. Only has an effect
+    /// with the `Puct` policy, and lasts until the next action. `epsilon` is how much weight the noise gets, from 0 to
+    /// 1, and smaller values of `alpha` concentrate the noise on fewer moves. 0.25 and 0.3 are reasonable choices.
+    pub fn add_root_noise(&mut self, epsilon: f64, alpha: f64) {
+        self.0.add_root_noise(epsilon, alpha);
+    }
+
+    /// Restricts the AI to the given actions until `clear_move_restriction` is called or an action is performed.
+    pub fn restrict_moves(&mut self, allowed: &[u8]) {
+        self.0.set_move_filter(allowed);
+    }
+
+    pub fn clear_move_restriction(&mut self) {
+        self.0.clear_move_filter();
     }
 
     pub fn current_player(&self) -> String {
-        match self.0.current_player() {
-            Player::X => "X".to_owned(),
-            Player::O => "O".to_owned()
+        player_name(self.0.current_player())
+    }
+
+    /// Returns the index of the sub-board the AI's current player has to play in, or None if they can play in any open
+    /// sub-board.
+    pub fn active_board(&self) -> Option<u8> {
+        self.0.root_state().active_board()
+    }
+
+    pub fn is_game_over(&self) -> bool {
+        self.0.is_game_over()
+    }
+
+    /// Returns "X" or "O" once that player has won the game, or None while the game is still going or if it was drawn.
+    pub fn winner(&self) -> Option<String> {
+        self.0.root_state().winner().map(player_name)
+    }
+
+    /// Returns whether the game is over without either player winning.
+    pub fn is_draw(&self) -> bool {
+        self.0.root_state().result() == Some(GameResult::Draw)
+    }
+
+    /// Returns how the game ended, like `Board::result`.
+    pub fn result(&self) -> Option<String> {
+        self.0.root_state().result().map(result_name)
+    }
+
+    /// Takes back the last move and restarts the search from the position before it, since the search so far was
+    /// for the position after the move. Returns false if there is no move to undo.
+    pub fn undo(&mut self) -> bool {
+        let mut state = self.0.root_state().clone();
+        if !state.undo() {
+            return false;
         }
+        self.0.reset_to(state);
+        true
+    }
+
+    /// Sets whether games that fill up without a winner go to the player who won more sub-boards. This restarts the
+    /// search, since the results so far were for the other rule.
+    pub fn set_subboard_tiebreak(&mut self, enabled: bool) {
+        let mut state = self.0.root_state().clone();
+        state.set_subboard_tiebreak(enabled);
+        self.0.reset_to(state);
+    }
+
+    /// Sets whether a draw counts as half a win for both players, rather than the default tenth. The AI is then happier
+    /// to settle for a draw, and winrates are expected scores. This restarts the search, since the results so far were
+    /// scored the other way.
+    pub fn set_draws_as_half_wins(&mut self, enabled: bool) {
+        self.0.set_scoring(if enabled { Scoring::draws_as_half_wins() } else { Scoring::default() });
+        let state = self.0.root_state().clone();
+        self.0.reset_to(state);
+    }
+
+    /// Starts a new game, keeping the AI's settings and opening book. On the web, the simulations are reseeded randomly
+    /// so that each game is played differently. Elsewhere they carry on from where the last game left off, so use
+    /// `reset_with_seed` to make a game reproducible.
+    pub fn reset(&mut self) {
+        self.0.reset_to(self.new_game());
+        reseed_randomly(&mut self.0);
+    }
+
+    /// Starts a new game like `reset`, with the simulations seeded with the given number (see `with_seed`).
+    pub fn reset_with_seed(&mut self, seed: f64) {
+        self.0.reset_to(self.new_game());
+        self.0.reseed(seed.to_bits());
+    }
+}
+
+impl UTTTMonteCarloAI {
+    // Creates an empty board with the same rules as the current game
+    fn new_game(&self) -> TicTacToe {
+        self.0.root_state().restart()
+    }
+}
+
+impl Default for UTTTMonteCarloAI {
+    fn default() -> Self {
+        UTTTMonteCarloAI::new()
+    }
+}
+
+/// An AI for regular 3x3 tic-tac-toe, with the same search as `UTTTMonteCarloAI`. Actions are the cells from 0 to 8,
+/// numbered row by row from the top left.
+#[wasm_bindgen]
+pub struct TTTMonteCarloAI(ActionTree<KInARow>);
+
+#[wasm_bindgen]
+impl TTTMonteCarloAI {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        TTTMonteCarloAI(ActionTree::new(KInARow::regular()))
+    }
+
+    /// Creates an AI whose simulations are seeded with the given number. See `UTTTMonteCarloAI::with_seed`.
+    pub fn with_seed(seed: f64) -> Self {
+        TTTMonteCarloAI(ActionTree::with_seed(KInARow::regular(), seed.to_bits()))
+    }
+
+    pub fn do_search_step(&mut self, num_sims: u32) {
+        self.0.do_search_step(num_sims);
+    }
+
+    /// Runs search steps of `sims_per_step` simulations each for `millis` milliseconds, and returns how many steps were
+    /// run. At least one step is always run.
+    pub fn search_for_ms(&mut self, millis: f64, sims_per_step: u32) -> u32 {
+        search_for_ms(&mut self.0, millis, sims_per_step)
+    }
+
+    pub fn get_best_action(&self) -> Option<ActionStats> {
+        self.0.get_best_action()
+            .map(|(action, node_id)| action_stats(&self.0, *action, node_id))
+    }
+
+    /// Gets statistics for every move the AI has considered, from the most to the least simulated.
+    pub fn get_all_action_stats(&self) -> Vec<ActionStats> {
+        all_action_stats(&self.0)
+    }
+
+    /// Plays a move, failing without changing anything if it isn't legal.
+    pub fn do_action(&mut self, action: u8) -> Result<(), JsValue> {
+        self.0.do_action(&action).map_err(|err| JsValue::from_str(&err.to_string()))
+    }
+
+    /// Sets the exploration constant used by the search, which defaults to sqrt(2).
+    pub fn set_exploration(&mut self, exploration: f64) {
+        self.0.set_exploration(exploration);
+    }
+
+    pub fn current_player(&self) -> String {
+        player_name(self.0.current_player())
     }
 
     pub fn is_game_over(&self) -> bool {
         self.0.is_game_over()
     }
 
+    /// Starts a new game, keeping the AI's settings. See `UTTTMonteCarloAI::reset`.
     pub fn reset(&mut self) {
-        self.0 = ActionTree::new(TicTacToe::new());
+        self.0.reset_to(KInARow::regular());
+        reseed_randomly(&mut self.0);
+    }
+
+    /// Starts a new game like `reset`, with the simulations seeded with the given number (see `with_seed`).
+    pub fn reset_with_seed(&mut self, seed: f64) {
+        self.0.reset_to(KInARow::regular());
+        self.0.reseed(seed.to_bits());
+    }
+}
+
+impl Default for TTTMonteCarloAI {
+    fn default() -> Self {
+        TTTMonteCarloAI::new()
+    }
+}
+
+/// A Connect Four board for a UI to draw and play on, like `Board` is for the ultimate game.
+#[wasm_bindgen]
+pub struct ConnectFourBoard(ConnectFour);
+
+#[wasm_bindgen]
+impl ConnectFourBoard {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        ConnectFourBoard(ConnectFour::new())
+    }
+
+    /// Draws the board `width` pixels wide. It's 6/7 as tall as it is wide.
+    pub fn draw(&self, ctx: &CanvasRenderingContext2d, width: f64) -> Result<(), JsValue> {
+        self.0.draw(ctx, check_size(width)?)
+    }
+
+    /// Finds the column a click dropped a disc into, if it can take another disc.
+    pub fn action_for_click(&self, x: f64, y: f64, width: f64) -> Result<Option<u8>, JsValue> {
+        Ok(self.0.action_for_click(check_coordinate(x)?, check_coordinate(y)?, check_size(width)?))
+    }
+
+    /// Drops a disc into a column, failing without changing anything if the column doesn't exist or is full.
+    pub fn do_action_mut(&mut self, action: u8) -> Result<(), JsValue> {
+        Ok(play_checked(&mut self.0, action)?)
+    }
+
+    pub fn current_player(&self) -> String {
+        player_name(self.0.current_player())
+    }
+
+    pub fn is_game_over(&self) -> bool {
+        self.0.game_over()
+    }
+
+    pub fn reset(&mut self) {
+        self.0 = ConnectFour::new();
+    }
+}
+
+impl Default for ConnectFourBoard {
+    fn default() -> Self {
+        ConnectFourBoard::new()
+    }
+}
+
+/// An AI for Connect Four, with the same search as `UTTTMonteCarloAI`. Actions are columns from 0 to 6.
+#[wasm_bindgen]
+pub struct ConnectFourMonteCarloAI(ActionTree<ConnectFour>);
+
+#[wasm_bindgen]
+impl ConnectFourMonteCarloAI {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        ConnectFourMonteCarloAI(ActionTree::new(ConnectFour::new()))
+    }
+
+    /// Creates an AI whose simulations are seeded with the given number. See `UTTTMonteCarloAI::with_seed`.
+    pub fn with_seed(seed: f64) -> Self {
+        ConnectFourMonteCarloAI(ActionTree::with_seed(ConnectFour::new(), seed.to_bits()))
+    }
+
+    pub fn do_search_step(&mut self, num_sims: u32) {
+        self.0.do_search_step(num_sims);
+    }
+
+    /// Runs search steps of `sims_per_step` simulations each for `millis` milliseconds, and returns how many steps were
+    /// run. At least one step is always run.
+    pub fn search_for_ms(&mut self, millis: f64, sims_per_step: u32) -> u32 {
+        search_for_ms(&mut self.0, millis, sims_per_step)
+    }
+
+    pub fn get_best_action(&self) -> Option<ActionStats> {
+        self.0.get_best_action()
+            .map(|(action, node_id)| action_stats(&self.0, *action, node_id))
+    }
+
+    /// Plays a move, failing without changing anything if it isn't legal.
+    pub fn do_action(&mut self, action: u8) -> Result<(), JsValue> {
+        self.0.do_action(&action).map_err(|err| JsValue::from_str(&err.to_string()))
+    }
+
+    pub fn current_player(&self) -> String {
+        player_name(self.0.current_player())
+    }
+
+    pub fn is_game_over(&self) -> bool {
+        self.0.is_game_over()
+    }
+
+    /// Starts a new game, keeping the AI's settings. See `UTTTMonteCarloAI::reset`.
+    pub fn reset(&mut self) {
+        self.0.reset_to(ConnectFour::new());
+        reseed_randomly(&mut self.0);
+    }
+
+    /// Starts a new game like `reset`, with the simulations seeded with the given number (see `with_seed`).
+    pub fn reset_with_seed(&mut self, seed: f64) {
+        self.0.reset_to(ConnectFour::new());
+        self.0.reseed(seed.to_bits());
+    }
+}
+
+impl Default for ConnectFourMonteCarloAI {
+    fn default() -> Self {
+        ConnectFourMonteCarloAI::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::SmallRng;
+
+    // The player to move can win the game immediately
+    const WON_POSITION: &str = "70 65 24 55 17 78 59 47 23 52 68 51 60 57 28 11 19 10 13 42 56 25 64 14 50 49 38 26 80 \
+        79 71 73 9 1 74 18 8 72 6 62 77 37 4 41 40 44 7 63 35 54 43 66 34 69 58 39";
+    // Every move the player to move can make lets their opponent win the game immediately
+    const LOST_POSITION: &str = "76 40 37 9 0 4 43 70 63 5 51 58 44 73 17 77 48 31 38 23 53 74 19 11 25 67 41 46 16 68 49 \
+        2 21 28 14 45 3 29 18 6 57 33 60 61 65 26 75 47";
+
+    // The player to move has exactly one move that wins the game immediately
+    const SINGLE_WINNING_MOVE: &str = "39 28 14 45 3 31 40 41 46 12 34 63 5 51 60 57 30 29 23 49 42 61 70 67 36 1 16 68 \
+        47 18 6 62 76 48 35 79 71 80 72 7 64 10 17 78 55 13 54 8 21 33 58 20 26 15 56";
+
+    #[test]
+    fn size_and_coordinate_checks_reject_bad_numbers() {
+        assert_eq!(check_size(300.0), Ok(300.0));
+        for &size in [f64::NAN, f64::INFINITY, -300.0, 0.0].iter() {
+            assert!(check_size(size).is_err());
+        }
+
+        assert_eq!(check_coordinate(-5.0), Ok(-5.0));
+        for &coordinate in [f64::NAN, f64::NEG_INFINITY].iter() {
+            assert!(check_coordinate(coordinate).is_err());
+        }
+    }
+
+    #[test]
+    fn budgets_from_javascript_are_clamped() {
+        assert_eq!(budget_from_millis(1500.0), Duration::from_millis(1500));
+        for &millis in [-5.0, f64::NAN, f64::NEG_INFINITY].iter() {
+            assert_eq!(budget_from_millis(millis), Duration::ZERO);
+        }
+        assert_eq!(budget_from_millis(f64::INFINITY), Duration::MAX);
+    }
+
+    #[test]
+    fn evaluate_positions_scores_won_and_lost_positions() {
+        let positions = vec![WON_POSITION.to_owned(), LOST_POSITION.to_owned()];
+        let scores = evaluate_positions(&positions, 2000);
+        assert_eq!(scores.len(), 2);
+        assert!(scores[0] > 0.9, "won position scored {}", scores[0]);
+        assert!(scores[1] < 0.1, "lost position scored {}", scores[1]);
+    }
+
+    #[test]
+    fn branching_narrows_over_a_game() {
+        let branching = branching_over_record(WON_POSITION).unwrap();
+        assert_eq!(branching.len(), WON_POSITION.split_whitespace().count() + 1);
+        assert_eq!(branching[0], 81);
+
+        let late = &branching[branching.len() - 10..];
+        assert!(late.iter().sum::<u32>() / 10 < 9);
+    }
+
+    #[test]
+    fn all_action_stats_cover_every_legal_move() {
+        let mut ai = UTTTMonteCarloAI(ActionTree::new(TicTacToe::from_game_record("36 4 37 13").unwrap()));
+        for _ in 0..300 {
+            ai.do_search_step(10);
+        }
+
+        let stats = ai.get_all_action_stats();
+        assert_eq!(stats.len(), ai.0.root_state().available_actions().len());
+        assert!(stats.windows(2).all(|pair| pair[0].sims >= pair[1].sims));
+        let (_, most_visited) = ai.0.most_visited_action().unwrap();
+        assert_eq!(stats[0].sims, ai.0.get_node_total_points(most_visited));
+    }
+
+    #[test]
+    fn evaluation_grid_covers_the_board() {
+        let mut ai = UTTTMonteCarloAI(ActionTree::new(TicTacToe::from_game_record("36 4 37 13").unwrap()));
+        for _ in 0..100 {
+            ai.do_search_step(10);
+        }
+
+        let grid = ai.evaluation_grid();
+        assert_eq!(grid.len(), 81);
+        let legal = ai.0.root_state().available_actions();
+        for (action, &winrate) in grid.iter().enumerate() {
+            if legal.contains(&(action as u8)) {
+                assert!((0.0..=1.0).contains(&winrate));
+            } else {
+                assert_eq!(winrate, -1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn child_stats_match_all_action_stats() {
+        let mut ai = UTTTMonteCarloAI::with_seed(2.0);
+        assert!(ai.child_stats(40).is_none());
+        for _ in 0..100 {
+            ai.do_search_step(10);
+        }
+
+        let all_stats = ai.get_all_action_stats();
+        for &action in ai.0.root_state().available_actions() {
+            let stats = ai.child_stats(action).unwrap();
+            let expected = all_stats.iter().find(|stats| stats.action == action).unwrap();
+            assert_eq!((stats.wins, stats.sims), (expected.wins, expected.sims));
+        }
+        assert!(ai.child_stats(81).is_none());
+    }
+
+    #[test]
+    fn boards_keep_their_rules_when_loading_moves() {
+        let mut board = Board::with_rules(SendToFullBoard::Forced, true, "O").unwrap();
+        board.load_moves(vec![40, 36]).unwrap();
+        let rules = Rules { send_rule: SendToFullBoard::Forced, subboard_tiebreak: true, starter: Player::O };
+        assert_eq!(board.0.rules(), rules);
+        assert_eq!(board.0.current_player(), Player::O);
+
+        let ai = UTTTMonteCarloAI::with_rules(SendToFullBoard::Forced, false, "O", 1.0).unwrap();
+        assert_eq!(ai.0.current_player(), Player::O);
+    }
+
+    #[test]
+    fn hints_are_legal_moves() {
+        let mut ai = UTTTMonteCarloAI::with_seed(1.0);
+        ai.do_action(40).unwrap();
+        for &sims in [0, 1, 500].iter() {
+            let hint = ai.hint(sims).unwrap();
+            assert!(ai.0.root_state().available_actions().contains(&hint.action));
+            assert!(hint.wins <= hint.sims);
+        }
+
+        let mut board = Board::new();
+        board.load_moves(vec![36, 4, 37, 13]).unwrap();
+        let code = board.to_code();
+        let hint = UTTTMonteCarloAI::hint_for(&board, 300).unwrap();
+        assert!(board.is_legal(hint.action));
+        assert!(hint.sims > 0);
+        assert_eq!(board.to_code(), code);
+
+        // With enough simulations the hint should be a move that wins
+        let won = Board(TicTacToe::from_game_record(WON_POSITION).unwrap(), Theme::default());
+        let hint = UTTTMonteCarloAI::hint_for(&won, 2000).unwrap();
+        assert!(hint.wins as f64 > 0.9 * hint.sims as f64, "hint won {} of {}", hint.wins, hint.sims);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn best_move_within_always_returns_a_legal_move() {
+        let mut ai = UTTTMonteCarloAI::new();
+        let action = ai.best_move_within(0.0, 1).unwrap();
+        assert!(ai.0.root_state().available_actions().contains(&action));
+
+        let mut ai = UTTTMonteCarloAI(ActionTree::new(TicTacToe::from_game_record(WON_POSITION).unwrap()));
+        let action = ai.best_move_within(20.0, 10).unwrap();
+        assert!(ai.0.root_state().available_actions().contains(&action));
+    }
+
+    #[test]
+    fn should_resign_only_when_lost() {
+        let mut lost = UTTTMonteCarloAI(ActionTree::new(TicTacToe::from_game_record(LOST_POSITION).unwrap()));
+        let mut opening = UTTTMonteCarloAI::new();
+        assert!(!lost.should_resign(0.2));
+        for _ in 0..500 {
+            lost.do_search_step(10);
+            opening.do_search_step(10);
+        }
+        assert!(lost.should_resign(0.2));
+        assert!(!opening.should_resign(0.2));
+    }
+
+    #[test]
+    fn subboard_win_probabilities_favor_near_won_subboard() {
+        // X has two in a row in sub-board 4 and has to play there
+        let mut ai = UTTTMonteCarloAI(ActionTree::new(TicTacToe::from_game_record("36 4 37 13").unwrap()));
+        for _ in 0..500 {
+            ai.do_search_step(10);
+        }
+
+        let probabilities = ai.subboard_win_probabilities();
+        assert_eq!(probabilities.len(), 9);
+        assert!(probabilities[4] > 0.5, "sub-board 4 probability was {}", probabilities[4]);
+        assert!((0..9).filter(|&i| i != 4).all(|i| probabilities[i] < probabilities[4]));
+    }
+
+    #[test]
+    fn replay_positions_includes_every_position() {
+        let positions = replay_positions(WON_POSITION).unwrap();
+        assert_eq!(positions.len(), WON_POSITION.split_whitespace().count() + 1);
+        assert_eq!(positions[0], TicTacToe::new().to_string());
+        assert_eq!(positions.last(), Some(&TicTacToe::from_game_record(WON_POSITION).unwrap().to_string()));
+    }
+
+    #[test]
+    fn principal_variation_ends_with_the_winning_move() {
+        let mut ai = UTTTMonteCarloAI(ActionTree::new(TicTacToe::from_game_record(SINGLE_WINNING_MOVE).unwrap()));
+        for _ in 0..1000 {
+            ai.do_search_step(10);
+        }
+
+        let player = ai.0.current_player();
+        let mut board = ai.0.root_state().clone();
+        let line = ai.principal_variation();
+        assert!(!line.is_empty());
+        for action in line {
+            board.do_action_mut(&action);
+        }
+        assert!(board.game_over());
+        assert_eq!(board.winner(), Some(player));
+    }
+
+    #[test]
+    fn positions_equal_detects_transpositions() {
+        assert!(positions_equal("75 35 78 62", "78 62 75 35"));
+        assert!(!positions_equal("75 35 78 62", "75 35 78"));
+        assert!(!positions_equal("75 35 78 62", "75 75"));
+    }
+
+    #[test]
+    fn runner_up_gap_reflects_position() {
+        let mut won = ActionTree::new(TicTacToe::from_game_record(SINGLE_WINNING_MOVE).unwrap());
+        let mut opening = ActionTree::new(TicTacToe::new());
+        for _ in 0..2000 {
+            won.do_search_step(10);
+            opening.do_search_step(10);
+        }
+
+        let (_, _, won_gap) = won.runner_up().unwrap();
+        let (_, _, opening_gap) = opening.runner_up().unwrap();
+        assert!(won_gap > 0.15, "gap with a winning move was {}", won_gap);
+        assert!(opening_gap < 0.1, "gap in the opening was {}", opening_gap);
+    }
+
+    #[test]
+    fn evaluate_positions_rejects_bad_records() {
+        let positions = vec!["40 40".to_owned(), "40 x".to_owned()];
+        assert!(evaluate_positions(&positions, 10).iter().all(|score| score.is_nan()));
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_search_agrees_with_single_search() {
+        let state = TicTacToe::from_game_record(SINGLE_WINNING_MOVE).unwrap();
+        let mut single = ActionTree::new(state.clone());
+        for _ in 0..2000 {
+            single.do_search_step(10);
+        }
+
+        let merged = ai::parallel_search(state, 20000, 4);
+        // Each tree's first step simulates the root itself rather than one of its children
+        let child_visits = merged.iter().map(|(_, visits, _)| visits).sum::<u32>();
+        assert_eq!(child_visits, 20000 - 4 * 10);
+        assert_eq!(Some(&merged[0].0), single.most_visited_action().map(|(action, _)| action));
+    }
+
+    #[test]
+    fn board_cells_and_subboard_winners() {
+        let mut board = Board::new();
+        for &action in [36, 4, 37, 13, 38].iter() {
+            board.do_action_mut(action).unwrap();
+        }
+
+        let cells = board.cells();
+        assert_eq!(cells.len(), 81);
+        assert_eq!((cells[36], cells[37], cells[38]), (1, 1, 1));
+        assert_eq!((cells[4], cells[13]), (2, 2));
+        assert_eq!(cells.iter().filter(|&&cell| cell != 0).count(), 5);
+
+        let mut winners = vec![0; 9];
+        winners[4] = 1;
+        assert_eq!(board.subboard_winners(), winners);
+    }
+
+    #[test]
+    fn undo_after_winning_move() {
+        let start = TicTacToe::from_game_record(SINGLE_WINNING_MOVE).unwrap();
+        let winning_move = *start.available_actions().iter()
+            .find(|&action| start.do_action(action).winner().is_some())
+            .unwrap();
+
+        let mut board = Board(start.clone(), Theme::default());
+        let player = board.current_player();
+        board.do_action_mut(winning_move).unwrap();
+        assert!(board.is_game_over());
+        assert!(board.undo());
+        assert!(!board.is_game_over());
+        assert_eq!(board.current_player(), player);
+        assert_eq!(board.0, start);
+
+        let mut ai = UTTTMonteCarloAI(ActionTree::new(start.clone()));
+        ai.do_search_step(10);
+        ai.do_action(winning_move).unwrap();
+        assert!(ai.is_game_over());
+        assert!(ai.undo());
+        assert!(!ai.is_game_over());
+        assert_eq!(ai.current_player(), player);
+        assert_eq!(ai.0.node_count(), 1);
+        assert_eq!(ai.0.root_state(), &start);
+    }
+
+    #[test]
+    fn set_theme_stores_colors() {
+        let mut board = Board::new();
+        assert_eq!(board.1, Theme::default());
+
+        board.set_theme("#eee", "orange", "#0af", "#430", "#034", "#444");
+        assert_eq!(board.1, Theme {
+            grid: "#eee".to_owned(),
+            x: "orange".to_owned(),
+            o: "#0af".to_owned(),
+            x_highlight: "#430".to_owned(),
+            o_highlight: "#034".to_owned(),
+            last_move: "#444".to_owned()
+        });
+
+        board.reset();
+        assert_eq!(board.1.x, "orange");
+    }
+
+    #[test]
+    fn reset_with_seed_clears_the_tree_and_reseeds() {
+        // Plays part of a game, then starts again from the seed and returns the move found by the same search
+        fn first_move(seed: f64) -> (u8, usize) {
+            let mut ai = UTTTMonteCarloAI::with_seed(0.0);
+            ai.set_subboard_tiebreak(true);
+            for _ in 0..20 {
+                ai.do_search_step(10);
+            }
+            let action = ai.get_best_action().unwrap().action;
+            ai.do_action(action).unwrap();
+
+            ai.reset_with_seed(seed);
+            assert!(!ai.0.is_game_over() && ai.0.root_state().subboard_tiebreak());
+            let node_count = ai.0.node_count();
+            for _ in 0..50 {
+                ai.do_search_step(10);
+            }
+            (ai.get_best_action().unwrap().action, node_count)
+        }
+
+        assert_eq!(first_move(1.0).1, 1);
+        assert_eq!(first_move(1.0), first_move(1.0));
+        assert_ne!(first_move(1.0).0, first_move(2.0).0);
+    }
+
+    // Searches the regular game's current position and returns the move the AI would play
+    fn ttt_move(ai: &mut TTTMonteCarloAI) -> u8 {
+        for _ in 0..2000 {
+            ai.do_search_step(10);
+        }
+        ai.get_best_action().unwrap().action
+    }
+
+    #[test]
+    fn ttt_ai_draws_against_itself() {
+        let mut ai = TTTMonteCarloAI::new();
+        while !ai.is_game_over() {
+            let action = ttt_move(&mut ai);
+            ai.do_action(action).unwrap();
+        }
+        assert_eq!(ai.0.root_state().winner(), None);
+    }
+
+    #[test]
+    fn ttt_reset_keeps_settings_and_reseeds() {
+        // Plays a move, then starts again from the seed and returns the move found by a short search
+        fn first_move(seed: f64) -> u8 {
+            let mut ai = TTTMonteCarloAI::with_seed(0.0);
+            ai.set_exploration(0.5);
+            let action = ttt_move(&mut ai);
+            ai.do_action(action).unwrap();
+
+            ai.reset_with_seed(seed);
+            assert!(!ai.is_game_over() && ai.0.node_count() == 1);
+            assert_eq!(ai.0.exploration(), 0.5);
+            for _ in 0..30 {
+                ai.do_search_step(1);
+            }
+            ai.get_best_action().unwrap().action
+        }
+
+        assert_eq!(first_move(1.0), first_move(1.0));
+        assert_ne!(first_move(1.0), first_move(2.0));
+
+        let mut ai = TTTMonteCarloAI::new();
+        ai.set_exploration(0.5);
+        ai.reset();
+        assert_eq!(ai.0.exploration(), 0.5);
+    }
+
+    #[test]
+    fn ttt_ai_takes_wins_and_blocks() {
+        // X has the left column's top two cells and can win at 6
+        let mut ai = TTTMonteCarloAI::new();
+        for &action in [0, 1, 3, 4].iter() {
+            ai.do_action(action).unwrap();
+        }
+        assert_eq!(ttt_move(&mut ai), 6);
+
+        // O must stop X from completing the top row at 2
+        let mut ai = TTTMonteCarloAI::new();
+        for &action in [0, 4, 1].iter() {
+            ai.do_action(action).unwrap();
+        }
+        assert_eq!(ttt_move(&mut ai), 2);
+    }
+
+    #[test]
+    fn connect_four_board_rejects_full_and_missing_columns() {
+        let mut board = ConnectFour::new();
+        for _ in 0..6 {
+            play_checked(&mut board, 3).unwrap();
+        }
+        let full = format!("{:?}", board);
+        assert!(play_checked(&mut board, 3).is_err());
+        assert!(play_checked(&mut board, 7).is_err());
+        assert_eq!(format!("{:?}", board), full);
+        assert_eq!(board.current_player(), Player::X);
+    }
+
+    #[test]
+    fn connect_four_reset_keeps_settings_and_reseeds() {
+        // Plays a move, then starts again from the seed and returns the move found by a short search
+        fn first_move(seed: f64) -> u8 {
+            let mut ai = ConnectFourMonteCarloAI::with_seed(0.0);
+            ai.0.set_exploration(0.5);
+            ai.do_search_step(10);
+            ai.do_action(3).unwrap();
+
+            ai.reset_with_seed(seed);
+            assert!(!ai.is_game_over() && ai.0.node_count() == 1);
+            assert_eq!(ai.0.exploration(), 0.5);
+            for _ in 0..30 {
+                ai.do_search_step(1);
+            }
+            ai.get_best_action().unwrap().action
+        }
+
+        assert_eq!(first_move(1.0), first_move(1.0));
+        assert_ne!(first_move(1.0), first_move(2.0));
+    }
+
+    #[test]
+    fn win_probability_reflects_position() {
+        let mut won = UTTTMonteCarloAI(ActionTree::new(TicTacToe::from_game_record(SINGLE_WINNING_MOVE).unwrap()));
+        let mut opening = UTTTMonteCarloAI::new();
+        assert_eq!(opening.win_probability(), 0.5);
+        for _ in 0..2000 {
+            won.do_search_step(10);
+            opening.do_search_step(10);
+        }
+
+        assert!(won.win_probability() > 0.9, "probability with a winning move was {}", won.win_probability());
+        // Draws only earn a tenth of a win, which pulls the estimate a little below 0.5 when neither side is ahead
+        let opening_probability = opening.win_probability();
+        assert!((opening_probability - 0.5).abs() < 0.15, "probability in the opening was {}", opening_probability);
+    }
+
+    #[test]
+    fn search_until_confident_stops_early_with_a_winning_move() {
+        let mut ai = UTTTMonteCarloAI(ActionTree::new(TicTacToe::from_game_record(SINGLE_WINNING_MOVE).unwrap()));
+        let sims = ai.search_until_confident(100, 0.9, 1_000_000);
+        assert!(sims < 100_000, "search ran {} simulations", sims);
+
+        let mut opening = UTTTMonteCarloAI::new();
+        assert_eq!(opening.search_until_confident(100, 0.9, 20_000), 20_000);
+    }
+
+    // Plays random games until one ends with the given winner, and returns its moves
+    fn random_game_won_by(winner: Option<Player>) -> Vec<u8> {
+        (0..).find_map(|seed| {
+            let mut rng = SmallRng::seed_from_u64(seed);
+            let mut state = TicTacToe::new();
+            let mut moves = Vec::new();
+            while let Some(action) = state.rollout_action(&mut rng) {
+                moves.push(action);
+                state.do_action_mut(&action);
+            }
+            Some(moves).filter(|_| state.winner() == winner)
+        }).unwrap()
+    }
+
+    #[test]
+    fn winner_and_draw_are_reported_at_the_end_of_a_game() {
+        let board = Board::new();
+        assert_eq!(board.winner(), None);
+        assert!(!board.is_draw());
+        assert_eq!(board.result(), None);
+
+        for &(winner, name) in [(Some(Player::X), Some("X")), (Some(Player::O), Some("O")), (None, None)].iter() {
+            let moves = random_game_won_by(winner);
+            let mut board = Board::new();
+            board.load_moves(moves.clone()).unwrap();
+            assert!(board.is_game_over());
+            assert_eq!(board.winner().as_deref(), name);
+            assert_eq!(board.is_draw(), winner.is_none());
+            assert_eq!(board.result().as_deref(), Some(name.unwrap_or("draw")));
+
+            let mut ai = UTTTMonteCarloAI::new();
+            for action in moves {
+                ai.do_action(action).unwrap();
+            }
+            assert_eq!(ai.winner().as_deref(), name);
+            assert_eq!(ai.is_draw(), winner.is_none());
+            assert_eq!(ai.result().as_deref(), Some(name.unwrap_or("draw")));
+        }
+    }
+
+    #[test]
+    fn legal_moves_match_available_actions() {
+        for record in ["", "40", "36 4 37 13 38", SINGLE_WINNING_MOVE, WON_POSITION].iter() {
+            let mut board = Board::new();
+            board.load_moves(record.split_whitespace().map(|action| action.parse().unwrap()).collect()).unwrap();
+            assert_eq!(board.legal_moves(), board.0.available_actions());
+        }
+
+        // After 40, O has to play in the center sub-board, and 40 itself is taken
+        let mut board = Board::new();
+        board.do_action_mut(40).unwrap();
+        assert!(board.is_legal(36));
+        assert!(!board.is_legal(40));
+        assert!(!board.is_legal(0));
+        assert!(!board.is_legal(81));
+
+        // Illegal moves are rejected without changing the board
+        let before = board.0.clone();
+        for &action in [40, 0, 81, 255].iter() {
+            assert!(play_checked(&mut board.0, action).is_err());
+        }
+        assert_eq!(board.0, before);
+        assert_eq!(board.0.last_action(), Some(40));
+    }
+
+    #[test]
+    fn active_board_follows_the_last_move() {
+        let mut board = Board::new();
+        let mut ai = UTTTMonteCarloAI::new();
+        assert_eq!(board.active_board(), None);
+        assert_eq!(ai.active_board(), None);
+
+        // Playing in a cell sends the opponent to the matching sub-board
+        for &(action, active) in [(40, Some(4)), (36, Some(0)), (4, Some(4)), (37, Some(1)), (13, Some(4))].iter() {
+            board.do_action_mut(action).unwrap();
+            ai.do_action(action).unwrap();
+            assert_eq!(board.active_board(), active);
+            assert_eq!(ai.active_board(), active);
+        }
+
+        // O wins the center sub-board with 38, so sending O back there with 22 frees them
+        board.do_action_mut(38).unwrap();
+        ai.do_action(38).unwrap();
+        assert_eq!(board.active_board(), Some(2));
+        board.do_action_mut(22).unwrap();
+        ai.do_action(22).unwrap();
+        assert_eq!(board.active_board(), None);
+        assert_eq!(ai.active_board(), None);
+    }
+}
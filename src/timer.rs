@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+/// Measures elapsed wall-clock time. `std::time::Instant` panics when targeting wasm, so the browser's high resolution
+/// timer is used there instead.
+pub struct Stopwatch {
+    #[cfg(target_arch = "wasm32")]
+    start: f64,
+    #[cfg(not(target_arch = "wasm32"))]
+    start: std::time::Instant
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Stopwatch {
+    pub fn start() -> Self {
+        Stopwatch { start: performance_now() }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_secs_f64((performance_now() - self.start).max(0.0) / 1000.0)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Stopwatch {
+    pub fn start() -> Self {
+        Stopwatch { start: std::time::Instant::now() }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// Gets the current time in milliseconds from `performance.now()`, which is available both on the main thread and in
+/// web workers, falling back to `Date.now()` if it's missing.
+#[cfg(target_arch = "wasm32")]
+fn performance_now() -> f64 {
+    use wasm_bindgen::JsCast;
+
+    js_sys::Reflect::get(&js_sys::global(), &"performance".into())
+        .ok()
+        .and_then(|performance| performance.dyn_into::<web_sys::Performance>().ok())
+        .map_or_else(js_sys::Date::now, |performance| performance.now())
+}
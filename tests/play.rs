@@ -0,0 +1,48 @@
+//! Plays scripted games through the terminal interface in `src/bin/play.rs`.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::io::Write;
+use std::process::{ Command, Stdio };
+
+// Runs the binary with the given arguments and input, and returns everything it printed
+fn play(args: &[&str], input: &str) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_play"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn scripted_game_against_the_ai_finishes() {
+    // Offer every cell in turn, over and over, so that each of the human's turns takes the next legal one. Illegal and
+    // unreadable moves are turned down, and the human is asked again.
+    let cells = (0..81).map(|action| action.to_string()).collect::<Vec<String>>().join("\n");
+    let input = format!("E0\n{}", vec![cells; 41].join("\n"));
+    let output = play(&["--sims", "50", "--seed", "3"], &input);
+
+    assert!(output.contains("Enter a move like E5"));
+    assert!(output.contains("O plays "));
+    let result = output.lines().last().unwrap();
+    assert!(result == "Game over: X wins" || result == "Game over: O wins" || result == "Game over: draw", "{}", result);
+}
+
+#[test]
+fn ai_plays_itself_without_input() {
+    let output = play(&["--sims", "20", "--human", "none"], "");
+    assert!(output.contains("X plays ") && output.contains("O plays "));
+    assert!(output.lines().last().unwrap().starts_with("Game over: "));
+}
+
+#[test]
+fn game_stops_when_input_runs_out() {
+    let output = play(&["--sims", "20", "--human", "O"], "");
+    assert!(output.contains("X plays "));
+    assert!(!output.contains("Game over"));
+}
@@ -11,3 +11,10 @@ wasm_bindgen_test_configure!(run_in_browser);
 fn pass() {
     assert_eq!(1 + 1, 2);
 }
+
+#[wasm_bindgen_test]
+fn board_snapshot_is_a_png_data_url() {
+    let board = uttt_ai::Board::new();
+    let url = board.to_png_data_url(90.0).unwrap();
+    assert!(url.starts_with("data:image/png;base64,"));
+}
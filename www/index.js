@@ -65,8 +65,14 @@ class UIManager {
     }
 
     onWindowResize() {
-        this.canvas.width = this.canvas_container.offsetWidth;
-        this.canvas.height = window.innerHeight;
+        // Size the canvas in device pixels so that it's sharp on high-DPI screens, while keeping its CSS size the same
+        this.pixel_ratio = window.devicePixelRatio || 1;
+        this.css_width = this.canvas_container.offsetWidth;
+        this.css_height = window.innerHeight;
+        this.canvas.width = this.css_width * this.pixel_ratio;
+        this.canvas.height = this.css_height * this.pixel_ratio;
+        this.canvas.style.width = this.css_width + "px";
+        this.canvas.style.height = this.css_height + "px";
         this.render_board();
     }
 
@@ -83,11 +89,20 @@ class UIManager {
 
         // Show the new game panel if the game ended
         if (this.game_manager.board.is_game_over()) {
+            var board = this.game_manager.board;
+            var result = board.result();
+            this.current_player.textContent = result == "draw" ? "Draw" : result + " wins";
             this.game_settings_panel.style.display = null;
             this.game_stats_panel.style.display = "none";
         }
     }
 
+    show_resignation(player) {
+        this.current_player.textContent = player + " resigns";
+        this.game_settings_panel.style.display = null;
+        this.game_stats_panel.style.display = "none";
+    }
+
     update_stats(stats) {
         this.thinking_time.textContent = Math.floor(stats.sim_time / 100) / 10
 
@@ -112,15 +127,34 @@ class UIManager {
         this.render_board();
     }
 
-    render_board() {
-        this.board_size = Math.min(this.canvas.width, this.canvas.height) * 0.9;
-        this.board_x = this.canvas.width / 2 - this.board_size / 2;
-        this.board_y = this.canvas.height / 2 - this.board_size / 2;
+    // Draws the last move's mark growing in over a fraction of a second
+    animate_last_move() {
+        const self = this;
+        const duration = 250;
+        var start = null;
+        function frame(time) {
+            if (start === null) {
+                start = time;
+            }
+            self.render_board((time - start) / duration);
+            if (time - start < duration) {
+                window.requestAnimationFrame(frame);
+            }
+        }
+        window.requestAnimationFrame(frame);
+    }
+
+    render_board(last_move_progress = 1) {
+        // Positions and sizes are in CSS pixels, which is what clicks are measured in
+        this.board_size = Math.min(this.css_width, this.css_height) * 0.9;
+        this.board_x = this.css_width / 2 - this.board_size / 2;
+        this.board_y = this.css_height / 2 - this.board_size / 2;
     
         this.canvas_ctx.resetTransform();
         this.canvas_ctx.clearRect(0, 0, this.canvas.width, this.canvas.height);
-        this.canvas_ctx.translate(this.board_x, this.board_y);
-        this.game_manager.board.draw(this.canvas_ctx, this.board_size);
+        this.canvas_ctx.translate(this.board_x * this.pixel_ratio, this.board_y * this.pixel_ratio);
+        this.game_manager.board.draw_animated(this.canvas_ctx, this.board_size, this.pixel_ratio, last_move_progress);
+        this.canvas_ctx.scale(this.pixel_ratio, this.pixel_ratio);
     
         // Only show the best action when the AI is playing (don't let human players cheat!)
         if (this.last_stats && this.game_manager.current_player_type == "ai") {
@@ -154,8 +188,11 @@ class GameManager {
             var msg = e.data;
             if (msg.type == "do_action") {
                 self.do_action(msg.action);
+                self.ui.animate_last_move();
             } else if (msg.type == "stats") {
                 self.ui.update_stats(msg);
+            } else if (msg.type == "resign") {
+                self.ui.show_resignation(msg.player);
             }
         }
     }
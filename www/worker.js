@@ -2,7 +2,6 @@ import * as wasm from "uttt-ai";
 
 wasm.set_panic_hook();
 
-var steps_per_round = 1;
 var sim_time = 0;
 var total_sims = 0;
 
@@ -14,7 +13,7 @@ var options = {
     playing_for: { X: false, O: false }
 }
 
-var ai = new wasm.UTTTMonteCarloAI();
+var ai = wasm.UTTTMonteCarloAI.with_seed(Date.now());
 
 onmessage = function(e) {
     var request = e.data;
@@ -47,18 +46,27 @@ function do_simulations() {
         return;
     }
 
+    // Play straight from the opening book when it has a move for this position
+    var book_action = ai.consult_book();
+    if (book_action !== undefined && options.playing_for[ai.current_player()]) {
+        postMessage({
+            type: "do_action",
+            action: book_action
+        });
+        ai.do_action(book_action);
+        sim_time = 0;
+        setTimeout(do_simulations, 0);
+        return;
+    }
+
     var sim_start = Date.now();
 
-    for (var i = 0; i < steps_per_round; i++) {
-        ai.do_search_step(options.simulations_per_step);
-    }
+    // Search for about target_round_time milliseconds
+    var steps = ai.search_for_ms(options.target_round_time, options.simulations_per_step);
 
-    var sim_count = steps_per_round * options.simulations_per_step;
+    var sim_count = steps * options.simulations_per_step;
     var round_time = Date.now() - sim_start;
 
-    // Update steps_per_round to try to make the next round of simulations take target_round_time milliseconds
-    steps_per_round = Math.floor(options.target_round_time / (round_time / steps_per_round));
-
     total_sims += sim_count;
     sim_time += round_time;
     var sim_rate = sim_count / (round_time / 1000)
@@ -76,7 +84,14 @@ function do_simulations() {
             sim_rate: sim_rate,
         });
 
-        if (sim_time >= options.thinking_time && options.playing_for[ai.current_player()]) {
+        if (sim_time >= options.thinking_time && options.playing_for[ai.current_player()] && ai.should_resign(0.02)) {
+            // Give up instead of playing out a hopeless position
+            postMessage({
+                type: "resign",
+                player: ai.current_player()
+            });
+            options.simulation_enabled = false;
+        } else if (sim_time >= options.thinking_time && options.playing_for[ai.current_player()]) {
             postMessage({
                 type: "do_action",
                 action: stats.action